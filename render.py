@@ -29,6 +29,17 @@ f.close()
 
 n_lines = len(file_lines)
 
+# index -> color, written next to the recording
+species_colors = {}
+try:
+    with open("simulations/simulation_species.txt", 'r') as species_file:
+        for line in species_file:
+            fields = line.split()
+            if len(fields) == 5:
+                species_colors[int(fields[0])] = (int(fields[2]), int(fields[3]), int(fields[4]))
+except FileNotFoundError:
+    pass
+
 def glob_to_screen(pos):
 
     norm_coord = (pos + pygame.Vector2(1, 1)) / 2
@@ -62,7 +73,7 @@ just_scrolled = False
 time_step_pressed = False
 s_button_pressed = False
 
-n_display_modes = 3
+n_display_modes = 4
 display_mode = 0
 
 marked_pos = pygame.Vector2(0, 0)
@@ -176,11 +187,14 @@ while running:
         color_draw = (200, 20, 20)
 
         if display_mode == 1:
-            speed_gradient = min(float(pos[2]) * 255 * 10000, 225) + 30
+            speed_gradient = min(float(pos[2]) * 255 * 10, 225) + 30
             color_draw = (speed_gradient, ) * 3
         elif display_mode == 2:
             collision_gradient = min(float(pos[3]) / 128 * 255, 225) + 30
             color_draw = (collision_gradient, ) * 3
+        elif display_mode == 3 and len(pos) > 4:
+            species_i = int(pos[4])
+            color_draw = species_colors.get(species_i, random_colors[species_i % len(random_colors)])
 
         # print(color_draw)
         draw_pos = (pygame.Vector2(float(pos[0]), float(pos[1])) - camera_offset) * true_zoom_val
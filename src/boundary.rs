@@ -0,0 +1,323 @@
+use crate::diagnostics::Diagnostics;
+use crate::particle::Particle;
+use crate::vector::Vec2;
+
+// how a wall responds to a hit: restitution scales the normal velocity, friction is the
+// coulomb coefficient acting on the tangential velocity
+#[derive(Clone, Copy)]
+pub struct WallMaterial {
+    pub restitution: f32,
+    pub friction: f32,
+}
+
+impl WallMaterial {
+    pub fn new(restitution: f32, friction: f32) -> WallMaterial {
+        return WallMaterial {
+            restitution,
+            friction,
+        };
+    }
+}
+
+// a single wall, both sides are solid so it works for containers and obstacles alike
+#[derive(Clone)]
+pub struct Segment {
+    pub start: Vec2,
+    pub end: Vec2,
+    pub material: WallMaterial,
+}
+
+impl Segment {
+    pub fn new(start: Vec2, end: Vec2, material: WallMaterial) -> Segment {
+        return Segment {
+            start,
+            end,
+            material,
+        };
+    }
+
+    pub fn closest_point(&self, pos: Vec2) -> Vec2 {
+        let dir = self.end - self.start;
+        let len_squared = dir.length_squared();
+
+        if len_squared == 0.0 {
+            return self.start;
+        }
+
+        let t = ((pos - self.start).dot(dir) / len_squared).clamp(0.0, 1.0);
+        return self.start + dir * t;
+    }
+
+    // signed distance of pos from the infinite line through the segment
+    pub fn side(&self, pos: Vec2) -> f32 {
+        return (self.end - self.start).perp().dot(pos - self.start);
+    }
+
+    // true if the path from -> to passes through the segment
+    pub fn is_crossed(&self, from: Vec2, to: Vec2) -> bool {
+        let side_from = self.side(from);
+        let side_to = self.side(to);
+
+        if side_from == 0.0 || (side_from > 0.0) == (side_to > 0.0) {
+            return false;
+        }
+
+        let path = Segment::new(from, to, WallMaterial::new(0.0, 0.0));
+        return (path.side(self.start) > 0.0) != (path.side(self.end) > 0.0);
+    }
+}
+
+#[derive(Clone)]
+pub struct CircleWall {
+    pub center: Vec2,
+    pub radius: f32,
+    pub material: WallMaterial,
+    // container keeps particles inside, otherwise it is an obstacle that keeps them out
+    pub is_container: bool,
+}
+
+impl CircleWall {
+    pub fn new(
+        center: Vec2,
+        radius: f32,
+        material: WallMaterial,
+        is_container: bool,
+    ) -> CircleWall {
+        return CircleWall {
+            center,
+            radius,
+            material,
+            is_container,
+        };
+    }
+}
+
+// NOTE: the quadtree only covers [-1, 1] so walls should stay inside that square
+#[derive(Clone)]
+pub struct Boundary {
+    pub segments: Vec<Segment>,
+    pub circles: Vec<CircleWall>,
+}
+
+impl Boundary {
+    pub fn new() -> Boundary {
+        return Boundary {
+            segments: Vec::new(),
+            circles: Vec::new(),
+        };
+    }
+
+    pub fn square(half_size: f32, material: WallMaterial) -> Boundary {
+        let mut boundary = Boundary::new();
+        boundary.add_polygon(
+            &[
+                Vec2::new(-half_size, -half_size),
+                Vec2::new(half_size, -half_size),
+                Vec2::new(half_size, half_size),
+                Vec2::new(-half_size, half_size),
+            ],
+            material,
+        );
+
+        return boundary;
+    }
+
+    pub fn circle(center: Vec2, radius: f32, material: WallMaterial) -> Boundary {
+        let mut boundary = Boundary::new();
+        boundary.add_circle_container(center, radius, material);
+
+        return boundary;
+    }
+
+    pub fn polygon(points: &[Vec2], material: WallMaterial) -> Boundary {
+        let mut boundary = Boundary::new();
+        boundary.add_polygon(points, material);
+
+        return boundary;
+    }
+
+    pub fn add_segment(&mut self, start: Vec2, end: Vec2, material: WallMaterial) {
+        self.segments.push(Segment::new(start, end, material));
+    }
+
+    // open polyline, e.g. the two sides of a funnel
+    pub fn add_chain(&mut self, points: &[Vec2], material: WallMaterial) {
+        for point_i in 1..points.len() {
+            self.add_segment(points[point_i - 1], points[point_i], material);
+        }
+    }
+
+    // closed polyline, convex or concave. also works as an internal obstacle
+    pub fn add_polygon(&mut self, points: &[Vec2], material: WallMaterial) {
+        if points.len() < 2 {
+            return;
+        }
+
+        self.add_chain(points, material);
+        self.add_segment(points[points.len() - 1], points[0], material);
+    }
+
+    pub fn add_circle_container(&mut self, center: Vec2, radius: f32, material: WallMaterial) {
+        self.circles
+            .push(CircleWall::new(center, radius, material, true));
+    }
+
+    pub fn add_circle_obstacle(&mut self, center: Vec2, radius: f32, material: WallMaterial) {
+        self.circles
+            .push(CircleWall::new(center, radius, material, false));
+    }
+
+    pub fn set_material(&mut self, material: WallMaterial) {
+        for segment in &mut self.segments {
+            segment.material = material;
+        }
+        for circle in &mut self.circles {
+            circle.material = material;
+        }
+    }
+
+    pub fn set_restitution(&mut self, restitution: f32) {
+        for segment in &mut self.segments {
+            segment.material.restitution = restitution;
+        }
+        for circle in &mut self.circles {
+            circle.material.restitution = restitution;
+        }
+    }
+
+    pub fn set_friction(&mut self, friction: f32) {
+        for segment in &mut self.segments {
+            segment.material.friction = friction;
+        }
+        for circle in &mut self.circles {
+            circle.material.friction = friction;
+        }
+    }
+
+    // n points away from the wall, towards where the particle should be
+    fn reflect(
+        particle: &mut Particle,
+        new_pos: Vec2,
+        n: Vec2,
+        material: WallMaterial,
+        diagnostics: &mut Diagnostics,
+    ) {
+        let vel = particle.get_vel();
+        let vel_n = vel.dot(n);
+
+        particle.pos = new_pos;
+
+        if vel_n >= 0.0 {
+            particle.set_vel(vel);
+            return;
+        }
+
+        let normal_impulse = -(1.0 + material.restitution) * vel_n;
+        let mut new_vel = vel + n * normal_impulse;
+
+        // coulomb friction, can only stop the sliding, never reverse it
+        let vel_t = vel - n * vel_n;
+        let speed_t = vel_t.length();
+        if speed_t > 0.0 {
+            let friction_impulse = (material.friction * normal_impulse).min(speed_t);
+            new_vel -= vel_t * (friction_impulse / speed_t);
+        }
+
+        particle.set_vel(new_vel);
+
+        diagnostics.n_wall_collisions += 1;
+        diagnostics.wall_energy_lost +=
+            0.5 * particle.mass * (vel.length_squared() - new_vel.length_squared());
+    }
+
+    pub fn resolve_particle(&self, particle: &mut Particle, diagnostics: &mut Diagnostics) {
+        // where the particle started this sub step, used to catch it tunneling through a wall
+        let start_pos = particle.prev_pos;
+
+        for segment in &self.segments {
+            let closest = segment.closest_point(particle.pos);
+
+            if segment.is_crossed(start_pos, particle.pos) {
+                let mut n = (segment.end - segment.start).perp().normalize();
+                if segment.side(start_pos) < 0.0 {
+                    n = -n;
+                }
+
+                Boundary::reflect(
+                    particle,
+                    closest + n * particle.radius,
+                    n,
+                    segment.material,
+                    diagnostics,
+                );
+                continue;
+            }
+
+            let delta = particle.pos - closest;
+            let dist_squared = delta.length_squared();
+
+            if dist_squared >= particle.radius * particle.radius {
+                continue;
+            }
+
+            let n = if dist_squared == 0.0 {
+                let mut n = (segment.end - segment.start).perp().normalize();
+                if segment.side(start_pos) < 0.0 {
+                    n = -n;
+                }
+                n
+            } else {
+                delta / dist_squared.sqrt()
+            };
+
+            Boundary::reflect(
+                particle,
+                closest + n * particle.radius,
+                n,
+                segment.material,
+                diagnostics,
+            );
+        }
+
+        for circle in &self.circles {
+            let delta = particle.pos - circle.center;
+            let dist_squared = delta.length_squared();
+
+            if circle.is_container {
+                let max_dist = circle.radius - particle.radius;
+
+                if dist_squared <= max_dist * max_dist || dist_squared == 0.0 {
+                    continue;
+                }
+
+                let outward = delta / dist_squared.sqrt();
+                Boundary::reflect(
+                    particle,
+                    circle.center + outward * max_dist,
+                    -outward,
+                    circle.material,
+                    diagnostics,
+                );
+            } else {
+                let min_dist = circle.radius + particle.radius;
+
+                if dist_squared >= min_dist * min_dist {
+                    continue;
+                }
+
+                let outward = if dist_squared == 0.0 {
+                    Vec2::new(1.0, 0.0)
+                } else {
+                    delta / dist_squared.sqrt()
+                };
+                Boundary::reflect(
+                    particle,
+                    circle.center + outward * min_dist,
+                    outward,
+                    circle.material,
+                    diagnostics,
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::ops::*;
+
+use crate::vector::Vec2;
+
+// double precision since the fmm expansions take high powers of small cell sizes
+#[derive(Clone, Copy)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Complex {
+        return Complex { re, im };
+    }
+
+    pub fn zero() -> Complex {
+        return Complex::new(0.0, 0.0);
+    }
+
+    pub fn one() -> Complex {
+        return Complex::new(1.0, 0.0);
+    }
+
+    pub fn from_vec2(vec: Vec2) -> Complex {
+        return Complex::new(vec.x as f64, vec.y as f64);
+    }
+
+    pub fn to_vec2(self) -> Vec2 {
+        return Vec2::new(self.re as f32, self.im as f32);
+    }
+
+    pub fn conj(&self) -> Complex {
+        return Complex::new(self.re, -self.im);
+    }
+
+    pub fn norm_squared(&self) -> f64 {
+        return self.re * self.re + self.im * self.im;
+    }
+
+    pub fn inverse(&self) -> Complex {
+        let n = self.norm_squared();
+        return Complex::new(self.re / n, -self.im / n);
+    }
+
+    // principal branch
+    pub fn sqrt(&self) -> Complex {
+        let r = self.norm_squared().sqrt();
+        let re = (0.5 * (r + self.re)).sqrt();
+        let im = (0.5 * (r - self.re)).sqrt();
+
+        if self.im < 0.0 {
+            return Complex::new(re, -im);
+        }
+        return Complex::new(re, im);
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+impl Mul<f64> for Complex {
+    type Output = Complex;
+
+    fn mul(self, scalar: f64) -> Complex {
+        Complex::new(self.re * scalar, self.im * scalar)
+    }
+}
+
+impl Neg for Complex {
+    type Output = Complex;
+
+    fn neg(self) -> Complex {
+        Complex::new(-self.re, -self.im)
+    }
+}
+
+impl AddAssign for Complex {
+    fn add_assign(&mut self, other: Complex) {
+        self.re += other.re;
+        self.im += other.im;
+    }
+}
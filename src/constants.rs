@@ -1,12 +1,26 @@
-pub const DAMPING: f32 = 0.999;
-pub const GLOBAL_GRAVITY_CONST: f32 = -1.0;
+pub const WALL_RESTITUTION: f32 = 1.0;
+pub const WALL_FRICTION: f32 = 0.0;
+pub const COLLISION_RESTITUTION: f32 = 1.0;
+pub const COLLISION_FRICTION: f32 = 0.0;
+
 pub const GRAVITY_CONST: f32 = 0.00000001;
 
 pub const THETA: f32 = 1.0;
 pub const THETA_SQUARED: f32 = THETA * THETA;
 
+// fmm opening angle has to stay below 1
+pub const FMM_THETA: f32 = 0.5;
+pub const FMM_ORDER: u32 = 6;
+
+// particle mesh cells per side, has to be a power of two
+pub const PM_GRID: u32 = 256;
+// treepm split radius in cells, the tree stops at SHORT_RANGE_CUTOFF split radii
+pub const PM_SPLIT_SCALE: f32 = 1.25;
+pub const SHORT_RANGE_CUTOFF: f32 = 4.5;
+
+// default softening length
 pub const EPS: f32 = 0.000001;
-pub const EPS_SQUARED: f32 = EPS * EPS;
 
-pub const MAX_SPEED: f32 = 0.001;
-pub const MAX_SPEED_SQUARED: f32 = MAX_SPEED * MAX_SPEED;
+// sph defaults, monatomic gas and the courant factor on h / signal speed
+pub const SPH_GAMMA: f32 = 5.0 / 3.0;
+pub const SPH_COURANT: f32 = 0.3;
@@ -0,0 +1,254 @@
+use rand::Rng;
+use rand_distr::StandardNormal;
+
+use crate::complex::Complex;
+use crate::constants::GRAVITY_CONST;
+use crate::fft::fft_2d;
+use crate::particle::Particle;
+use crate::species::Species;
+use crate::vector::Vec2;
+
+use std::f64::consts::PI;
+
+// the periodic box is the [-1, 1] square of the quadtree and the mesh
+pub const BOX_SIZE: f32 = 2.0;
+
+// steps for the growth factor integral
+const GROWTH_STEPS: usize = 2000;
+
+// comoving integration in an expanding background. positions are comoving and velocities are
+// their time derivative, which gives x'' = -2 H x' + g / a^3 with g the periodic mesh gravity.
+// 1/r gravity in 2d has no friedmann model of its own, so the particles are columns through a
+// cube of the box size (see ParticleMesh::set_columns) and their masses give the usual
+// 4 pi G rho = 3/2 omega_m H0^2 a^-3
+#[derive(Clone, Copy)]
+pub struct Cosmology {
+    // H0 in inverse simulation time, 0 turns it off
+    pub hubble: f32,
+    pub omega_matter: f32,
+    pub omega_lambda: f32,
+    pub omega_radiation: f32,
+    pub start_redshift: f32,
+
+    // zel'dovich initial conditions, grid_size^2 particles with P(k) ~ k^spectral_index
+    pub grid_size: u32,
+    pub spectral_index: f32,
+    // rms density contrast on the particle grid at the start
+    pub sigma: f32,
+}
+
+impl Cosmology {
+    pub fn new(hubble: f32, omega_matter: f32, omega_lambda: f32) -> Cosmology {
+        return Cosmology {
+            hubble,
+            omega_matter,
+            omega_lambda,
+            omega_radiation: 0.0,
+            start_redshift: 50.0,
+            grid_size: 64,
+            spectral_index: -1.0,
+            sigma: 0.05,
+        };
+    }
+
+    pub fn disabled() -> Cosmology {
+        return Cosmology::new(0.0, 1.0, 0.0);
+    }
+
+    // flat and matter only, a ~ t^(2/3)
+    pub fn einstein_de_sitter(hubble: f32) -> Cosmology {
+        return Cosmology::new(hubble, 1.0, 0.0);
+    }
+
+    pub fn set_radiation(&mut self, omega_radiation: f32) {
+        self.omega_radiation = omega_radiation;
+    }
+
+    pub fn set_start_redshift(&mut self, redshift: f32) {
+        self.start_redshift = redshift;
+    }
+
+    // grid_size has to be a power of two for the fft
+    pub fn set_initial_conditions(&mut self, grid_size: u32, spectral_index: f32, sigma: f32) {
+        self.grid_size = grid_size;
+        self.spectral_index = spectral_index;
+        self.sigma = sigma;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        return self.hubble > 0.0;
+    }
+
+    pub fn get_start_scale_factor(&self) -> f32 {
+        return 1.0 / (1.0 + self.start_redshift);
+    }
+
+    pub fn get_n_particles(&self) -> u32 {
+        return self.grid_size * self.grid_size;
+    }
+
+    // H / H0, whatever is left over from the omegas is curvature
+    fn expansion_rate(&self, a: f64) -> f64 {
+        let omega_matter = self.omega_matter as f64;
+        let omega_lambda = self.omega_lambda as f64;
+        let omega_radiation = self.omega_radiation as f64;
+        let omega_curvature = 1.0 - omega_matter - omega_lambda - omega_radiation;
+
+        return (omega_radiation / (a * a * a * a)
+            + omega_matter / (a * a * a)
+            + omega_curvature / (a * a)
+            + omega_lambda)
+            .sqrt();
+    }
+
+    pub fn get_hubble(&self, a: f32) -> f32 {
+        return self.hubble * self.expansion_rate(a as f64) as f32;
+    }
+
+    // rk4 on da/dt = a H(a)
+    pub fn advance(&self, a: f32, dt: f32) -> f32 {
+        let hubble = self.hubble as f64;
+        let dt = dt as f64;
+        let rate = |a: f64| a * hubble * self.expansion_rate(a);
+
+        let a = a as f64;
+        let k1 = rate(a);
+        let k2 = rate(a + 0.5 * dt * k1);
+        let k3 = rate(a + 0.5 * dt * k2);
+        let k4 = rate(a + dt * k3);
+
+        return (a + dt * (k1 + 2.0 * k2 + 2.0 * k3 + k4) / 6.0) as f32;
+    }
+
+    // linear growth, D = a early on. the integral form ignores the radiation
+    pub fn growth_factor(&self, a: f32) -> f32 {
+        let a = a as f64;
+        let da = a / GROWTH_STEPS as f64;
+
+        let mut integral = 0.0;
+        for step_i in 0..GROWTH_STEPS {
+            let x = (step_i as f64 + 0.5) * da;
+            let ae = x * self.expansion_rate(x);
+            integral += da / (ae * ae * ae);
+        }
+
+        return (2.5 * self.omega_matter as f64 * self.expansion_rate(a) * integral) as f32;
+    }
+
+    // f = dln D / dln a
+    pub fn growth_rate(&self, a: f32) -> f32 {
+        let epsilon = 0.001;
+        let upper = self.growth_factor(a * (1.0 + epsilon));
+        let lower = self.growth_factor(a * (1.0 - epsilon));
+
+        return (upper / lower).ln() / ((1.0 + epsilon) / (1.0 - epsilon)).ln();
+    }
+
+    // mass per particle that gives the background density for n_particles in the box
+    pub fn get_particle_mass(&self, n_particles: u32) -> f32 {
+        let volume = BOX_SIZE * BOX_SIZE * BOX_SIZE;
+        let mass = 1.5 * self.omega_matter * self.hubble * self.hubble * volume
+            / (4.0 * std::f32::consts::PI * GRAVITY_CONST);
+
+        return mass / n_particles as f32;
+    }
+
+    // a displaced grid of particles of the species. the density field is gaussian with the
+    // power law spectrum, the displacement psi solves div psi = -delta and the growing mode
+    // velocity is f H psi
+    pub fn zeldovich(&self, species: &Species, species_i: u32) -> Vec<Particle> {
+        let n = self.grid_size as usize;
+        let mut rng = rand::rng();
+
+        let mut delta = vec![Complex::zero(); n * n];
+        for value in delta.iter_mut() {
+            value.re = rng.sample(StandardNormal);
+        }
+        fft_2d(&mut delta, n, false);
+
+        let k_unit = 2.0 * PI / BOX_SIZE as f64;
+        let mut psi_x = vec![Complex::zero(); n * n];
+        let mut psi_y = vec![Complex::zero(); n * n];
+        for y in 0..n {
+            for x in 0..n {
+                let kx = (if x <= n / 2 {
+                    x as f64
+                } else {
+                    x as f64 - n as f64
+                }) * k_unit;
+                let ky = (if y <= n / 2 {
+                    y as f64
+                } else {
+                    y as f64 - n as f64
+                }) * k_unit;
+                let k_squared = kx * kx + ky * ky;
+
+                let idx = y * n + x;
+                if k_squared == 0.0 {
+                    delta[idx] = Complex::zero();
+                    continue;
+                }
+
+                let amplitude = k_squared.powf(0.25 * self.spectral_index as f64);
+                delta[idx] = delta[idx] * amplitude;
+
+                // i k delta / k^2
+                let rotated = Complex::new(-delta[idx].im, delta[idx].re) * (1.0 / k_squared);
+                psi_x[idx] = rotated * kx;
+                psi_y[idx] = rotated * ky;
+            }
+        }
+
+        fft_2d(&mut delta, n, true);
+        fft_2d(&mut psi_x, n, true);
+        fft_2d(&mut psi_y, n, true);
+
+        let mut variance = 0.0;
+        for value in &delta {
+            variance += value.re * value.re;
+        }
+        let rms = (variance / (n * n) as f64).sqrt();
+        let scale = if rms > 0.0 {
+            self.sigma as f64 / rms
+        } else {
+            0.0
+        };
+
+        let a = self.get_start_scale_factor();
+        let velocity_factor = self.growth_rate(a) * self.get_hubble(a);
+        let mass = self.get_particle_mass(self.get_n_particles());
+        let spacing = BOX_SIZE / n as f32;
+
+        let mut particles = Vec::new();
+        for y in 0..n {
+            for x in 0..n {
+                let idx = y * n + x;
+                let grid_pos = Vec2::new(
+                    -1.0 + (x as f32 + 0.5) * spacing,
+                    -1.0 + (y as f32 + 0.5) * spacing,
+                );
+                let displacement = Vec2::new(
+                    (psi_x[idx].re * scale) as f32,
+                    (psi_y[idx].re * scale) as f32,
+                );
+
+                let mut particle = species.make_particle(species_i);
+                particle.set_pos(wrap(grid_pos + displacement));
+                particle.set_vel(displacement * velocity_factor);
+                particle.mass = mass;
+                particle.update_inertia();
+                particles.push(particle);
+            }
+        }
+
+        return particles;
+    }
+}
+
+// back into the periodic box
+pub fn wrap(pos: Vec2) -> Vec2 {
+    return Vec2::new(
+        (pos.x + 1.0).rem_euclid(BOX_SIZE) - 1.0,
+        (pos.y + 1.0).rem_euclid(BOX_SIZE) - 1.0,
+    );
+}
@@ -0,0 +1,77 @@
+// counters accumulated by the container between two recorded frames
+pub struct Diagnostics {
+    pub n_wall_collisions: u32,
+    pub wall_energy_lost: f32,
+    pub total_wall_energy_lost: f32,
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        return Diagnostics {
+            n_wall_collisions: 0,
+            wall_energy_lost: 0.0,
+            total_wall_energy_lost: 0.0,
+        };
+    }
+
+    pub fn reset(&mut self) {
+        self.total_wall_energy_lost += self.wall_energy_lost;
+        self.n_wall_collisions = 0;
+        self.wall_energy_lost = 0.0;
+    }
+}
+
+pub struct DiagnosticsStep {
+    pub time: f32,
+    pub n_sub_steps: u32,
+    pub kinetic_energy: f32,
+    pub potential_energy: f32,
+    pub thermal_energy: f32,
+    pub redshift: f32,
+    pub n_wall_collisions: u32,
+    pub wall_energy_lost: f32,
+    pub total_wall_energy_lost: f32,
+}
+
+impl DiagnosticsStep {
+    pub const HEADER: &str = "time sub_steps kinetic potential total wall_collisions wall_energy_lost total_wall_energy_lost thermal redshift";
+
+    pub fn new(
+        time: f32,
+        n_sub_steps: u32,
+        kinetic_energy: f32,
+        potential_energy: f32,
+        thermal_energy: f32,
+        redshift: f32,
+        diagnostics: &Diagnostics,
+    ) -> DiagnosticsStep {
+        return DiagnosticsStep {
+            time,
+            n_sub_steps,
+            kinetic_energy,
+            potential_energy,
+            thermal_energy,
+            redshift,
+            n_wall_collisions: diagnostics.n_wall_collisions,
+            wall_energy_lost: diagnostics.wall_energy_lost,
+            total_wall_energy_lost: diagnostics.total_wall_energy_lost
+                + diagnostics.wall_energy_lost,
+        };
+    }
+
+    pub fn to_line(&self) -> String {
+        return format!(
+            "{} {} {} {} {} {} {} {} {} {}",
+            self.time,
+            self.n_sub_steps,
+            self.kinetic_energy,
+            self.potential_energy,
+            self.kinetic_energy + self.potential_energy + self.thermal_energy,
+            self.n_wall_collisions,
+            self.wall_energy_lost,
+            self.total_wall_energy_lost,
+            self.thermal_energy,
+            self.redshift
+        );
+    }
+}
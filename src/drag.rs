@@ -0,0 +1,118 @@
+use crate::constants::GRAVITY_CONST;
+use crate::vector::Vec2;
+
+// the stopping time is the same for every particle of the species
+pub const FIXED_DRAG: u32 = 0;
+// small grains in a thin gas, t_s ~ radius
+pub const EPSTEIN_DRAG: u32 = 1;
+// grains bigger than the mean free path, t_s ~ radius^2
+pub const STOKES_DRAG: u32 = 2;
+
+// the same wind everywhere
+pub const UNIFORM_FLOW: u32 = 0;
+// circular orbits around center, v = factor * sqrt(G M / r)
+pub const KEPLERIAN_FLOW: u32 = 1;
+
+// friction against the background medium, dv/dt = (v_gas - v) / t_s. the stopping time is
+// given at reference_radius and the regime scales it for the other particle sizes of the
+// species. 0 turns it off
+#[derive(Clone, Copy)]
+pub struct Drag {
+    pub regime: u32,
+    pub stopping_time: f32,
+    // 0 takes the species radius
+    pub reference_radius: f32,
+}
+
+impl Drag {
+    pub fn new(regime: u32, stopping_time: f32, reference_radius: f32) -> Drag {
+        return Drag {
+            regime,
+            stopping_time,
+            reference_radius,
+        };
+    }
+
+    pub fn disabled() -> Drag {
+        return Drag::new(FIXED_DRAG, 0.0, 0.0);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        return self.stopping_time > 0.0;
+    }
+
+    pub fn get_stopping_time(&self, radius: f32) -> f32 {
+        if self.reference_radius <= 0.0 {
+            return self.stopping_time;
+        }
+
+        let ratio = radius / self.reference_radius;
+        if self.regime == EPSTEIN_DRAG {
+            return self.stopping_time * ratio;
+        }
+        if self.regime == STOKES_DRAG {
+            return self.stopping_time * ratio * ratio;
+        }
+        return self.stopping_time;
+    }
+
+    // exact for a constant gas velocity over dt, so stopping times far below the step are fine
+    pub fn apply(&self, vel: Vec2, gas_vel: Vec2, radius: f32, dt: f32) -> Vec2 {
+        let stopping_time = self.get_stopping_time(radius);
+        if stopping_time <= 0.0 {
+            return vel;
+        }
+
+        return gas_vel + (vel - gas_vel) * (-dt / stopping_time).exp();
+    }
+}
+
+// what the background medium is doing, in the inertial frame
+#[derive(Clone, Copy)]
+pub struct BackgroundFlow {
+    pub kind: u32,
+    // only for the uniform flow
+    pub velocity: Vec2,
+    pub center: Vec2,
+    pub mass: f32,
+    // below 1 for pressure supported gas, that is what makes dust drift inwards
+    pub factor: f32,
+}
+
+impl BackgroundFlow {
+    pub fn uniform(velocity: Vec2) -> BackgroundFlow {
+        return BackgroundFlow {
+            kind: UNIFORM_FLOW,
+            velocity,
+            center: Vec2::zero(),
+            mass: 0.0,
+            factor: 1.0,
+        };
+    }
+
+    // counter clockwise like the rotating frame
+    pub fn keplerian(center: Vec2, mass: f32, factor: f32) -> BackgroundFlow {
+        return BackgroundFlow {
+            kind: KEPLERIAN_FLOW,
+            velocity: Vec2::zero(),
+            center,
+            mass,
+            factor,
+        };
+    }
+
+    pub fn get_velocity(&self, pos: Vec2) -> Vec2 {
+        if self.kind != KEPLERIAN_FLOW {
+            return self.velocity;
+        }
+
+        let offset = pos - self.center;
+        let distance = offset.length();
+        if distance == 0.0 {
+            return Vec2::zero();
+        }
+
+        let speed = self.factor * (GRAVITY_CONST * self.mass / distance).sqrt();
+        return offset.perp() * (speed / distance);
+    }
+}
@@ -0,0 +1,63 @@
+// things that happen to particles mid run, logged by id since indices shuffle every step
+
+pub struct MergeEvent {
+    pub time: f32,
+    pub parent_ids: [u32; 2],
+    pub child_id: u32,
+    pub child_mass: f32,
+}
+
+impl MergeEvent {
+    pub const HEADER: &str = "time parent_1 parent_2 child child_mass";
+
+    pub fn to_line(&self) -> String {
+        return format!(
+            "{} {} {} {} {}",
+            self.time, self.parent_ids[0], self.parent_ids[1], self.child_id, self.child_mass
+        );
+    }
+}
+
+pub struct FragmentEvent {
+    pub time: f32,
+    pub parent_ids: [u32; 2],
+    pub child_ids: Vec<u32>,
+    pub total_mass: f32,
+}
+
+impl FragmentEvent {
+    pub const HEADER: &str = "time parent_1 parent_2 total_mass children...";
+
+    pub fn to_line(&self) -> String {
+        let mut line = format!(
+            "{} {} {} {}",
+            self.time, self.parent_ids[0], self.parent_ids[1], self.total_mass
+        );
+        for child_id in &self.child_ids {
+            line.push_str(&format!(" {}", child_id));
+        }
+
+        return line;
+    }
+}
+
+// a sink swallowing a particle. a sink forming shows up as it accreting itself, with its own
+// mass as both the accreted and the sink mass
+pub struct AccretionEvent {
+    pub time: f32,
+    pub sink_id: u32,
+    pub particle_id: u32,
+    pub accreted_mass: f32,
+    pub sink_mass: f32,
+}
+
+impl AccretionEvent {
+    pub const HEADER: &str = "time sink particle accreted_mass sink_mass";
+
+    pub fn to_line(&self) -> String {
+        return format!(
+            "{} {} {} {} {}",
+            self.time, self.sink_id, self.particle_id, self.accreted_mass, self.sink_mass
+        );
+    }
+}
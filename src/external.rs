@@ -0,0 +1,212 @@
+use crate::constants::GRAVITY_CONST;
+use crate::vector::Vec2;
+
+// constant acceleration everywhere, phi = -field . pos
+pub const UNIFORM_FIELD: u32 = 0;
+// phi = -G M / r
+pub const POINT_MASS_POTENTIAL: u32 = 1;
+// phi = -G M / sqrt(r^2 + a^2)
+pub const KEPLER_POTENTIAL: u32 = 2;
+// phi = v0^2 / 2 ln(r^2 + rc^2), flat rotation curve at v0 past the core
+pub const LOGARITHMIC_POTENTIAL: u32 = 3;
+// phi = -G M ln(1 + r / rs) / r, M is the characteristic mass 4 pi rho0 rs^3
+pub const NFW_POTENTIAL: u32 = 4;
+
+// a fixed analytic potential on top of the particle gravity, e.g. a halo that is not worth
+// simulating with particles. masses are in particle units so they go through GRAVITY_CONST
+// like everything else. the radial ones work like the softening kernels:
+// acc = -offset * force_factor, jerk = -(vel * force_factor + offset * jerk_factor * offset.vel)
+#[derive(Clone, Copy)]
+pub struct ExternalPotential {
+    pub kind: u32,
+    pub center: Vec2,
+    // only for the uniform field
+    pub field: Vec2,
+    // mass, or v0^2 for the logarithmic halo
+    pub strength: f32,
+    // kepler softening, logarithmic core or nfw scale radius
+    pub scale: f32,
+    // left out of the diagnostics energy when false, e.g. for a field that is only there to
+    // push things around
+    pub is_in_energy: bool,
+}
+
+impl ExternalPotential {
+    fn new(kind: u32, center: Vec2, field: Vec2, strength: f32, scale: f32) -> ExternalPotential {
+        return ExternalPotential {
+            kind,
+            center,
+            field,
+            strength,
+            scale,
+            is_in_energy: true,
+        };
+    }
+
+    pub fn uniform(field: Vec2) -> ExternalPotential {
+        return ExternalPotential::new(UNIFORM_FIELD, Vec2::zero(), field, 0.0, 0.0);
+    }
+
+    pub fn point_mass(center: Vec2, mass: f32) -> ExternalPotential {
+        return ExternalPotential::new(POINT_MASS_POTENTIAL, center, Vec2::zero(), mass, 0.0);
+    }
+
+    pub fn kepler(center: Vec2, mass: f32, softening: f32) -> ExternalPotential {
+        return ExternalPotential::new(KEPLER_POTENTIAL, center, Vec2::zero(), mass, softening);
+    }
+
+    pub fn logarithmic(center: Vec2, circular_vel: f32, core_radius: f32) -> ExternalPotential {
+        return ExternalPotential::new(
+            LOGARITHMIC_POTENTIAL,
+            center,
+            Vec2::zero(),
+            circular_vel * circular_vel,
+            core_radius,
+        );
+    }
+
+    pub fn nfw(center: Vec2, mass: f32, scale_radius: f32) -> ExternalPotential {
+        return ExternalPotential::new(NFW_POTENTIAL, center, Vec2::zero(), mass, scale_radius);
+    }
+
+    pub fn set_in_energy(&mut self, is_in_energy: bool) {
+        self.is_in_energy = is_in_energy;
+    }
+
+    // acc = -offset * this, 0 at the center where the direction is undefined
+    fn force_factor(&self, distance_squared: f32) -> f32 {
+        let gm = (GRAVITY_CONST * self.strength) as f64;
+        let r2 = distance_squared as f64;
+
+        let factor = if self.kind == POINT_MASS_POTENTIAL {
+            if r2 == 0.0 {
+                0.0
+            } else {
+                gm / (r2 * r2.sqrt())
+            }
+        } else if self.kind == KEPLER_POTENTIAL {
+            let soft_squared = r2 + (self.scale * self.scale) as f64;
+            if soft_squared == 0.0 {
+                0.0
+            } else {
+                gm / (soft_squared * soft_squared.sqrt())
+            }
+        } else if self.kind == LOGARITHMIC_POTENTIAL {
+            let core_squared = r2 + (self.scale * self.scale) as f64;
+            if core_squared == 0.0 {
+                0.0
+            } else {
+                self.strength as f64 / core_squared
+            }
+        } else if self.kind == NFW_POTENTIAL {
+            // double precision, the two terms nearly cancel well inside the scale radius
+            if r2 == 0.0 {
+                0.0
+            } else {
+                let r = r2.sqrt();
+                let rs = self.scale as f64;
+                gm * ((r / rs).ln_1p() / (r2 * r) - 1.0 / (r2 * (rs + r)))
+            }
+        } else {
+            0.0
+        };
+
+        return factor as f32;
+    }
+
+    // 2 d(force_factor) / d(r^2)
+    fn jerk_factor(&self, distance_squared: f32) -> f32 {
+        let gm = (GRAVITY_CONST * self.strength) as f64;
+        let r2 = distance_squared as f64;
+
+        let factor = if self.kind == POINT_MASS_POTENTIAL {
+            if r2 == 0.0 {
+                0.0
+            } else {
+                -3.0 * gm / (r2 * r2 * r2.sqrt())
+            }
+        } else if self.kind == KEPLER_POTENTIAL {
+            let soft_squared = r2 + (self.scale * self.scale) as f64;
+            if soft_squared == 0.0 {
+                0.0
+            } else {
+                -3.0 * gm / (soft_squared * soft_squared * soft_squared.sqrt())
+            }
+        } else if self.kind == LOGARITHMIC_POTENTIAL {
+            let core_squared = r2 + (self.scale * self.scale) as f64;
+            if core_squared == 0.0 {
+                0.0
+            } else {
+                -2.0 * self.strength as f64 / (core_squared * core_squared)
+            }
+        } else if self.kind == NFW_POTENTIAL {
+            if r2 == 0.0 {
+                0.0
+            } else {
+                let r = r2.sqrt();
+                let rs = self.scale as f64;
+                gm * (3.0 / (r2 * r * (rs + r)) - 3.0 * (r / rs).ln_1p() / (r2 * r2)
+                    + 1.0 / (r2 * (rs + r) * (rs + r)))
+                    / r
+            }
+        } else {
+            0.0
+        };
+
+        return factor as f32;
+    }
+
+    pub fn acceleration(&self, pos: Vec2) -> Vec2 {
+        if self.kind == UNIFORM_FIELD {
+            return self.field;
+        }
+
+        let offset = pos - self.center;
+        return -offset * self.force_factor(offset.length_squared());
+    }
+
+    pub fn jerk(&self, pos: Vec2, vel: Vec2) -> Vec2 {
+        if self.kind == UNIFORM_FIELD {
+            return Vec2::zero();
+        }
+
+        let offset = pos - self.center;
+        let distance_squared = offset.length_squared();
+        return -(vel * self.force_factor(distance_squared)
+            + offset * (self.jerk_factor(distance_squared) * offset.dot(vel)));
+    }
+
+    // per unit mass
+    pub fn potential(&self, pos: Vec2) -> f32 {
+        let gm = GRAVITY_CONST * self.strength;
+        let offset = pos - self.center;
+        let distance_squared = offset.length_squared();
+
+        if self.kind == UNIFORM_FIELD {
+            return -self.field.dot(pos);
+        } else if self.kind == POINT_MASS_POTENTIAL {
+            if distance_squared == 0.0 {
+                return 0.0;
+            }
+            return -gm / distance_squared.sqrt();
+        } else if self.kind == KEPLER_POTENTIAL {
+            let soft_squared = distance_squared + self.scale * self.scale;
+            if soft_squared == 0.0 {
+                return 0.0;
+            }
+            return -gm / soft_squared.sqrt();
+        } else if self.kind == LOGARITHMIC_POTENTIAL {
+            return 0.5 * self.strength * (distance_squared + self.scale * self.scale).ln();
+        } else if self.kind == NFW_POTENTIAL {
+            // -G M / rs at the center
+            let r = distance_squared.sqrt() as f64;
+            let rs = self.scale as f64;
+            if r == 0.0 {
+                return -gm / self.scale;
+            }
+            return (-(gm as f64) * (r / rs).ln_1p() / r) as f32;
+        }
+
+        return 0.0;
+    }
+}
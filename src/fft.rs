@@ -0,0 +1,68 @@
+use crate::complex::Complex;
+
+// in place radix 2 fft, the length has to be a power of two. the inverse is normalised
+pub fn fft(data: &mut [Complex], is_inverse: bool) {
+    let n = data.len();
+
+    // bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+
+    let sign = if is_inverse { 1.0 } else { -1.0 };
+
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * 2.0 * std::f64::consts::PI / len as f64;
+        let root = Complex::new(angle.cos(), angle.sin());
+
+        for start in (0..n).step_by(len) {
+            let mut twiddle = Complex::one();
+            for k in 0..len / 2 {
+                let even = data[start + k];
+                let odd = data[start + k + len / 2] * twiddle;
+
+                data[start + k] = even + odd;
+                data[start + k + len / 2] = even - odd;
+                twiddle = twiddle * root;
+            }
+        }
+
+        len <<= 1;
+    }
+
+    if is_inverse {
+        let scale = 1.0 / n as f64;
+        for value in data.iter_mut() {
+            *value = *value * scale;
+        }
+    }
+}
+
+// row major n x n grid, rows first then columns through a scratch column
+pub fn fft_2d(data: &mut [Complex], n: usize, is_inverse: bool) {
+    for row in data.chunks_mut(n) {
+        fft(row, is_inverse);
+    }
+
+    let mut column = vec![Complex::zero(); n];
+    for x in 0..n {
+        for y in 0..n {
+            column[y] = data[y * n + x];
+        }
+        fft(&mut column, is_inverse);
+        for y in 0..n {
+            data[y * n + x] = column[y];
+        }
+    }
+}
@@ -0,0 +1,667 @@
+use crate::complex::Complex;
+use crate::constants::EPS;
+use crate::qtree::{QNode, QuadTree};
+use crate::softening::{PLUMMER_SOFTENING, Softening};
+use crate::vector::Vec2;
+
+// nodes with at most this many particles skip the expansions and work on the particles directly
+const BUCKET_SIZE: u32 = 8;
+
+// fast multipole gravity on top of the quadtree, same 1/r potential as get_grav_force.
+// with z = x + iy the kernel splits into 1/|z - w| = (z - w)^(-1/2) * conj(z - w)^(-1/2), so
+// every expansion is a double series in z and conj(z):
+//   multipole about c: sum_jk c_j c_k M_jk (z - c)^(-j-1/2) conj(z - c)^(-k-1/2)
+//                      with M_jk = sum m (w - c)^j conj(w - c)^k and c_j = (2j)! / (4^j j!^2)
+//   local about c:     sum_ln L_ln (z - c)^l conj(z - c)^n
+// both are cut off at j, k, l, n <= order. the expansions sit on the node bound centers
+pub struct Fmm {
+    order: usize,
+    // two nodes interact through their expansions when r1 + r2 < theta * distance, has to
+    // stay below 1 for the series to converge
+    theta: f64,
+    // only for the particles summed directly, the expansions are plain 1/r
+    softening: Softening,
+
+    multipoles: Vec<Complex>,
+    locals: Vec<Complex>,
+    // per small node, nodes summed directly and far nodes whose multipoles are evaluated
+    // directly at each particle, cheaper than going through a local expansion for a few particles
+    near: Vec<Vec<usize>>,
+    far: Vec<Vec<usize>>,
+    // particles under each node
+    counts: Vec<u32>,
+    // expansion index of each node. only the big nodes and the topmost small nodes get one, the
+    // nodes under those never go through the expansions
+    slots: Vec<u32>,
+
+    // binom(n, k) for n, k <= order
+    binomials: Vec<f64>,
+    // c_j * binom(-j - 1/2, l), the multipole to local coefficients without the powers of z
+    translations: Vec<f64>,
+
+    pair_stack: Vec<(usize, usize)>,
+    scratch_a: Vec<Complex>,
+    scratch_t: Vec<Complex>,
+    scratch_out: Vec<Complex>,
+}
+
+impl Fmm {
+    pub fn new(order: usize, theta: f32) -> Self {
+        let mut fmm = Fmm {
+            order: 0,
+            theta: theta as f64,
+            softening: Softening::new(PLUMMER_SOFTENING, EPS),
+            multipoles: Vec::new(),
+            locals: Vec::new(),
+            near: Vec::new(),
+            far: Vec::new(),
+            counts: Vec::new(),
+            slots: Vec::new(),
+            binomials: Vec::new(),
+            translations: Vec::new(),
+            pair_stack: Vec::new(),
+            scratch_a: Vec::new(),
+            scratch_t: Vec::new(),
+            scratch_out: Vec::new(),
+        };
+        fmm.set_order(order);
+
+        return fmm;
+    }
+
+    pub fn set_order(&mut self, order: usize) {
+        self.order = order;
+        let w = order + 1;
+
+        self.binomials = vec![0.0; w * w];
+        for n in 0..w {
+            self.binomials[n * w] = 1.0;
+            for k in 1..=n {
+                self.binomials[n * w + k] =
+                    self.binomials[(n - 1) * w + k - 1] + self.binomials[(n - 1) * w + k];
+            }
+        }
+
+        self.translations = vec![0.0; w * w];
+        let mut c_j = 1.0;
+        for j in 0..w {
+            if j > 0 {
+                c_j *= (2 * j - 1) as f64 / (2 * j) as f64;
+            }
+
+            let exponent = -(j as f64) - 0.5;
+            let mut binom = 1.0;
+            for l in 0..w {
+                if l > 0 {
+                    binom *= (exponent - (l - 1) as f64) / l as f64;
+                }
+                self.translations[j * w + l] = c_j * binom;
+            }
+        }
+
+        self.scratch_a = vec![Complex::zero(); w * w];
+        self.scratch_t = vec![Complex::zero(); w * w];
+        self.scratch_out = vec![Complex::zero(); w * w];
+    }
+
+    pub fn set_theta(&mut self, theta: f32) {
+        self.theta = theta as f64;
+    }
+
+    pub fn set_softening(&mut self, softening: Softening) {
+        self.softening = softening;
+    }
+
+    fn n_coeffs(&self) -> usize {
+        return (self.order + 1) * (self.order + 1);
+    }
+
+    // where the expansions of node_i start, only nodes with a slot have any
+    fn start(&self, node_i: usize) -> usize {
+        return self.slots[node_i] as usize * self.n_coeffs();
+    }
+
+    fn binomial(&self, n: usize, k: usize) -> f64 {
+        return self.binomials[n * (self.order + 1) + k];
+    }
+
+    // d^0 ..= d^n_powers-1
+    fn powers(d: Complex, n_powers: usize) -> Vec<Complex> {
+        let mut powers = Vec::with_capacity(n_powers);
+        let mut power = Complex::one();
+        for _ in 0..n_powers {
+            powers.push(power);
+            power = power * d;
+        }
+
+        return powers;
+    }
+
+    fn node_radius(node: &QNode) -> f64 {
+        // half the diagonal of the square
+        return (node.bound.size_squared as f64 * 0.5).sqrt();
+    }
+
+    fn particle_to_multipole(&mut self, node_i: usize, mass: f64, offset: Complex) {
+        let w = self.order + 1;
+        let start = self.start(node_i);
+        let powers = Fmm::powers(offset, w);
+
+        for j in 0..w {
+            for k in 0..w {
+                self.multipoles[start + j * w + k] += powers[j] * powers[k].conj() * mass;
+            }
+        }
+    }
+
+    // every translation is out = A^T x conj(A), done as two matrix products so it costs order^3
+    fn translate(a: &[Complex], x: &[Complex], tmp: &mut [Complex], out: &mut [Complex], w: usize) {
+        for j in 0..w {
+            for n in 0..w {
+                let mut sum = Complex::zero();
+                for k in 0..w {
+                    sum += x[j * w + k] * a[k * w + n].conj();
+                }
+                tmp[j * w + n] = sum;
+            }
+        }
+
+        for l in 0..w {
+            for n in 0..w {
+                let mut sum = Complex::zero();
+                for j in 0..w {
+                    sum += a[j * w + l] * tmp[j * w + n];
+                }
+                out[l * w + n] = sum;
+            }
+        }
+    }
+
+    // d is the child center minus the parent center, A_lj = binom(j, l) d^(j-l)
+    fn multipole_to_multipole(&mut self, child_i: usize, parent_i: usize, d: Complex) {
+        let w = self.order + 1;
+        let n_coeffs = self.n_coeffs();
+        let child_start = self.start(child_i);
+        let parent_start = self.start(parent_i);
+        let powers = Fmm::powers(d, w);
+
+        for l in 0..w {
+            for j in 0..w {
+                self.scratch_a[l * w + j] = if l <= j {
+                    powers[j - l] * self.binomial(j, l)
+                } else {
+                    Complex::zero()
+                };
+            }
+        }
+
+        Fmm::translate(
+            &self.scratch_a,
+            &self.multipoles[child_start..child_start + n_coeffs],
+            &mut self.scratch_t,
+            &mut self.scratch_out,
+            w,
+        );
+        for coeff_i in 0..n_coeffs {
+            self.multipoles[parent_start + coeff_i] += self.scratch_out[coeff_i];
+        }
+    }
+
+    // z is the target center minus the source center, A_jl = c_j binom(-j - 1/2, l) z^(-j-l-1/2)
+    fn multipole_to_local(&mut self, source_i: usize, target_i: usize, z: Complex) {
+        let w = self.order + 1;
+        let n_coeffs = self.n_coeffs();
+        let source_start = self.start(source_i);
+        let target_start = self.start(target_i);
+
+        // z^(-n-1/2), the sign of the root cancels against its conjugate
+        let inverse = z.inverse();
+        let mut inverse_powers = Fmm::powers(inverse, 2 * w - 1);
+        let root = inverse.sqrt();
+        for power in &mut inverse_powers {
+            *power = *power * root;
+        }
+
+        for j in 0..w {
+            for l in 0..w {
+                self.scratch_a[j * w + l] = inverse_powers[j + l] * self.translations[j * w + l];
+            }
+        }
+
+        Fmm::translate(
+            &self.scratch_a,
+            &self.multipoles[source_start..source_start + n_coeffs],
+            &mut self.scratch_t,
+            &mut self.scratch_out,
+            w,
+        );
+        for coeff_i in 0..n_coeffs {
+            self.locals[target_start + coeff_i] += self.scratch_out[coeff_i];
+        }
+    }
+
+    // d is the child center minus the parent center, A_la = binom(l, a) d^(l-a)
+    fn local_to_local(&mut self, parent_i: usize, child_i: usize, d: Complex) {
+        let w = self.order + 1;
+        let n_coeffs = self.n_coeffs();
+        let parent_start = self.start(parent_i);
+        let child_start = self.start(child_i);
+        let powers = Fmm::powers(d, w);
+
+        for l in 0..w {
+            for a in 0..w {
+                self.scratch_a[l * w + a] = if l >= a {
+                    powers[l - a] * self.binomial(l, a)
+                } else {
+                    Complex::zero()
+                };
+            }
+        }
+
+        Fmm::translate(
+            &self.scratch_a,
+            &self.locals[parent_start..parent_start + n_coeffs],
+            &mut self.scratch_t,
+            &mut self.scratch_out,
+            w,
+        );
+        for coeff_i in 0..n_coeffs {
+            self.locals[child_start + coeff_i] += self.scratch_out[coeff_i];
+        }
+    }
+
+    // a single far particle straight into the local expansion, only the M_00 = m term of the above.
+    // z is the target center minus the particle position
+    fn particle_to_local(&mut self, target_i: usize, mass: f64, z: Complex) {
+        let w = self.order + 1;
+        let target_start = self.start(target_i);
+
+        let inverse = z.inverse();
+        let mut a = Fmm::powers(inverse, w);
+        let root = inverse.sqrt();
+        for (l, a_l) in a.iter_mut().enumerate() {
+            *a_l = *a_l * root * self.translations[l];
+        }
+
+        for l in 0..w {
+            for n in 0..w {
+                self.locals[target_start + l * w + n] += a[l] * a[n].conj() * mass;
+            }
+        }
+    }
+
+    // gradient of the multipole expansion of source_i at pos,
+    // 2 d/dconj(z) = -sum_jk (2k + 1) c_j c_k M_jk z^(-j-1/2) conj(z)^(-k-3/2)
+    fn multipole_to_particle(&self, source_i: usize, center: Vec2, pos: Vec2) -> Complex {
+        let w = self.order + 1;
+        let source_start = self.start(source_i);
+
+        let inverse = (Complex::from_vec2(pos) - Complex::from_vec2(center)).inverse();
+        let mut inverse_powers = Fmm::powers(inverse, w + 1);
+        let root = inverse.sqrt();
+        for power in &mut inverse_powers {
+            *power = *power * root;
+        }
+
+        let mut force = Complex::zero();
+        for j in 0..w {
+            for k in 0..w {
+                let coeff =
+                    self.translations[j * w] * self.translations[k * w] * (2 * k + 1) as f64;
+                force += self.multipoles[source_start + j * w + k]
+                    * inverse_powers[j]
+                    * inverse_powers[k + 1].conj()
+                    * coeff;
+            }
+        }
+
+        return -force;
+    }
+
+    // pre-order walk over the leaves under node_i, relies on the last descendant pointing at
+    // the same next as node_i
+    fn next_leaf(tree: &QuadTree, root_i: usize, node_i: usize) -> Option<usize> {
+        let mut node_i = node_i;
+        loop {
+            if node_i == tree.stack[root_i].next {
+                return None;
+            }
+            if tree.stack[node_i].is_leaf {
+                return Some(node_i);
+            }
+            node_i = tree.stack[node_i].children;
+        }
+    }
+
+    fn first_leaf(tree: &QuadTree, node_i: usize) -> Option<usize> {
+        if tree.stack[node_i].is_leaf {
+            return Some(node_i);
+        }
+        return Fmm::next_leaf(tree, node_i, tree.stack[node_i].children);
+    }
+
+    // direct sum of every particle under source_i, same softening as the tree
+    fn direct_force(&self, tree: &QuadTree, source_i: usize, pos: Vec2, softening: f32) -> Vec2 {
+        let mut force = Vec2::zero();
+
+        let mut leaf = Fmm::first_leaf(tree, source_i);
+        while let Some(leaf_i) = leaf {
+            let source = &tree.stack[leaf_i];
+            let delta = source.center_mass - pos;
+            let distance_squared = delta.length_squared();
+
+            let length = softening.max(source.max_softening);
+            force +=
+                delta * (source.total_mass * self.softening.force_factor(distance_squared, length));
+
+            if leaf_i == source_i {
+                break;
+            }
+            leaf = Fmm::next_leaf(tree, source_i, source.next);
+        }
+
+        return force;
+    }
+
+    fn is_small(&self, node_i: usize) -> bool {
+        return self.counts[node_i] <= BUCKET_SIZE;
+    }
+
+    // builds every expansion, expects the quadtree to be built with propogate_mass done
+    pub fn compute(&mut self, tree: &QuadTree) {
+        let n_nodes = tree.stack.len();
+        let n_coeffs = self.n_coeffs();
+
+        self.counts.clear();
+        self.counts.resize(n_nodes, 0);
+        for nd_i in (0..n_nodes).rev() {
+            let node = &tree.stack[nd_i];
+            if node.is_leaf {
+                self.counts[nd_i] = (node.particle_contained != -1) as u32;
+            } else {
+                for child_i in node.children..node.children + 4 {
+                    self.counts[nd_i] += self.counts[child_i];
+                }
+            }
+        }
+
+        // parents always come before their children in the stack
+        self.slots.clear();
+        self.slots.resize(n_nodes, u32::MAX);
+        let mut n_slots = 0;
+        if self.counts[0] != 0 {
+            self.slots[0] = 0;
+            n_slots = 1;
+        }
+        for nd_i in 0..n_nodes {
+            let node = &tree.stack[nd_i];
+            if self.slots[nd_i] == u32::MAX || node.is_leaf || self.is_small(nd_i) {
+                continue;
+            }
+
+            for child_i in node.children..node.children + 4 {
+                if self.counts[child_i] != 0 {
+                    self.slots[child_i] = n_slots;
+                    n_slots += 1;
+                }
+            }
+        }
+
+        self.multipoles.clear();
+        self.multipoles
+            .resize(n_slots as usize * n_coeffs, Complex::zero());
+        self.locals.clear();
+        self.locals
+            .resize(n_slots as usize * n_coeffs, Complex::zero());
+        for near in &mut self.near {
+            near.clear();
+        }
+        self.near.resize(n_slots as usize, Vec::new());
+        for far in &mut self.far {
+            far.clear();
+        }
+        self.far.resize(n_slots as usize, Vec::new());
+
+        // upward pass, the small nodes straight from their particles
+        for nd_i in (0..n_nodes).rev() {
+            if self.slots[nd_i] == u32::MAX {
+                continue;
+            }
+
+            let node = &tree.stack[nd_i];
+            let center = Complex::from_vec2(node.bound.center);
+
+            if self.is_small(nd_i) {
+                let mut leaf = Fmm::first_leaf(tree, nd_i);
+                while let Some(leaf_i) = leaf {
+                    let leaf_node = &tree.stack[leaf_i];
+                    if leaf_node.particle_contained != -1 {
+                        let offset = Complex::from_vec2(leaf_node.center_mass) - center;
+                        self.particle_to_multipole(nd_i, leaf_node.total_mass as f64, offset);
+                    }
+
+                    if leaf_i == nd_i {
+                        break;
+                    }
+                    leaf = Fmm::next_leaf(tree, nd_i, leaf_node.next);
+                }
+                continue;
+            }
+
+            for child_i in node.children..node.children + 4 {
+                if self.slots[child_i] == u32::MAX {
+                    continue;
+                }
+
+                let d = Complex::from_vec2(tree.stack[child_i].bound.center) - center;
+                self.multipole_to_multipole(child_i, nd_i, d);
+            }
+        }
+
+        // dual tree walk. small nodes are handled particle by particle, everything else is
+        // either far enough for the expansions or split into the children of the bigger node
+        self.pair_stack.clear();
+        self.pair_stack.push((0, 0));
+
+        while let Some((target_i, source_i)) = self.pair_stack.pop() {
+            let target = &tree.stack[target_i];
+            let source = &tree.stack[source_i];
+
+            if self.counts[target_i] == 0 || source.total_mass == 0.0 {
+                continue;
+            }
+
+            let is_target_small = self.is_small(target_i);
+            let is_source_small = self.is_small(source_i);
+
+            if target_i == source_i {
+                if is_target_small {
+                    self.near[self.slots[target_i] as usize].push(source_i);
+                } else {
+                    for target_child_i in target.children..target.children + 4 {
+                        for source_child_i in target.children..target.children + 4 {
+                            self.pair_stack.push((target_child_i, source_child_i));
+                        }
+                    }
+                }
+                continue;
+            }
+
+            let z =
+                Complex::from_vec2(target.bound.center) - Complex::from_vec2(source.bound.center);
+            let radii = Fmm::node_radius(target) + Fmm::node_radius(source);
+            // the expansions are plain 1/r so nothing may be close enough to feel the softening
+            let support = self
+                .softening
+                .get_support(target.max_softening.max(source.max_softening))
+                as f64;
+            let is_separated = radii * radii < self.theta * self.theta * z.norm_squared()
+                && radii + support < z.norm_squared().sqrt();
+
+            if is_target_small && is_source_small {
+                self.near[self.slots[target_i] as usize].push(source_i);
+            } else if is_separated {
+                if is_source_small {
+                    let center = Complex::from_vec2(target.bound.center);
+
+                    let mut leaf = Fmm::first_leaf(tree, source_i);
+                    while let Some(leaf_i) = leaf {
+                        let source_leaf = &tree.stack[leaf_i];
+                        if source_leaf.total_mass != 0.0 {
+                            let z = center - Complex::from_vec2(source_leaf.center_mass);
+                            self.particle_to_local(target_i, source_leaf.total_mass as f64, z);
+                        }
+
+                        if leaf_i == source_i {
+                            break;
+                        }
+                        leaf = Fmm::next_leaf(tree, source_i, source_leaf.next);
+                    }
+                } else if is_target_small {
+                    self.far[self.slots[target_i] as usize].push(source_i);
+                } else {
+                    self.multipole_to_local(source_i, target_i, z);
+                }
+            } else if is_source_small
+                || (!is_target_small && target.bound.size_squared >= source.bound.size_squared)
+            {
+                for child_i in target.children..target.children + 4 {
+                    self.pair_stack.push((child_i, source_i));
+                }
+            } else {
+                for child_i in source.children..source.children + 4 {
+                    self.pair_stack.push((target_i, child_i));
+                }
+            }
+        }
+
+        // downward pass, parents are done before their children. small nodes keep their
+        // expansion, the particles under them read it directly
+        for nd_i in 0..n_nodes {
+            if tree.stack[nd_i].is_leaf || self.is_small(nd_i) {
+                continue;
+            }
+
+            let node = &tree.stack[nd_i];
+            let center = Complex::from_vec2(node.bound.center);
+            for child_i in node.children..node.children + 4 {
+                if self.counts[child_i] == 0 {
+                    continue;
+                }
+
+                let d = Complex::from_vec2(tree.stack[child_i].bound.center) - center;
+                self.local_to_local(nd_i, child_i, d);
+            }
+        }
+    }
+
+    // same units as QuadTree::get_grav_force, only valid after compute on the same tree
+    pub fn get_grav_force(&self, tree: &QuadTree, pos: Vec2, softening: f32) -> Vec2 {
+        // an empty tree never got any slots
+        if self.counts[0] == 0 {
+            return Vec2::zero();
+        }
+
+        let w = self.order + 1;
+
+        // the first small node on the way down holds everything this particle needs
+        let mut node_i = 0;
+        while !self.is_small(node_i) {
+            node_i = tree.idx_pos_single(node_i, pos);
+        }
+        let node = &tree.stack[node_i];
+        let start = self.start(node_i);
+
+        // the force is the gradient of the local expansion, d/dx + i d/dy = 2 d/dconj(z)
+        let u = Complex::from_vec2(pos) - Complex::from_vec2(node.bound.center);
+        let powers = Fmm::powers(u, w);
+
+        let mut force = Complex::zero();
+        for l in 0..w {
+            for n in 1..w {
+                force += self.locals[start + l * w + n]
+                    * powers[l]
+                    * powers[n - 1].conj()
+                    * (2.0 * n as f64);
+            }
+        }
+        for &source_i in &self.far[self.slots[node_i] as usize] {
+            force += self.multipole_to_particle(source_i, tree.stack[source_i].bound.center, pos);
+        }
+        let mut force = force.to_vec2();
+
+        for &source_i in &self.near[self.slots[node_i] as usize] {
+            force += self.direct_force(tree, source_i, pos, softening);
+        }
+
+        return force;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::constants::EPS;
+    use crate::particle::Particle;
+    use crate::particle_container::Container;
+    use crate::softening::{PLUMMER_SOFTENING, SPLINE_SOFTENING, Softening};
+    use crate::vector::Vec2;
+
+    // a sunflower spiral, spread out without needing an rng
+    fn spiral_container(n_particles: usize, radius: f32, softening: Softening) -> Container {
+        let mut container = Container::new();
+        container.softening = softening;
+        container.quadtree.set_softening(softening);
+        container.fmm.set_softening(softening);
+        for particle_i in 0..n_particles {
+            let angle = particle_i as f32 * 2.3999632;
+            let distance = radius * ((particle_i as f32 + 0.5) / n_particles as f32).sqrt();
+            let mut particle = Particle::new();
+            particle.set_pos(Vec2::new(angle.cos(), angle.sin()) * distance);
+            particle.mass = 1.0 + (particle_i % 3) as f32;
+            container.push_particle(particle);
+        }
+        return container;
+    }
+
+    fn direct_accelerations(container: &mut Container) -> Vec<Vec2> {
+        container.reset_acc();
+        container.interparticle_gravity_quadratic();
+        return container
+            .particles
+            .iter()
+            .map(|particle| particle.acc)
+            .collect();
+    }
+
+    fn max_fmm_error(container: &mut Container) -> f32 {
+        let direct = direct_accelerations(container);
+
+        container.reset_acc();
+        container.rebuild_quadtree();
+        container.interparticle_gravity_fmm();
+
+        let mut max_error: f32 = 0.0;
+        for (particle, acc) in container.particles.iter().zip(&direct) {
+            max_error = max_error.max((particle.acc - *acc).length() / acc.length());
+        }
+        return max_error;
+    }
+
+    #[test]
+    fn matches_direct_sum() {
+        let mut container = spiral_container(500, 0.6, Softening::new(PLUMMER_SOFTENING, EPS));
+        let max_error = max_fmm_error(&mut container);
+        assert!(max_error < 1e-3, "fmm max relative error {}", max_error);
+    }
+
+    // softening lengths around the particle spacing, the far field has to stay out of the
+    // kernel for both of them
+    #[test]
+    fn matches_softened_direct_sum() {
+        for kernel in [PLUMMER_SOFTENING, SPLINE_SOFTENING] {
+            let mut container = spiral_container(500, 0.6, Softening::new(kernel, 0.02));
+            let max_error = max_fmm_error(&mut container);
+            assert!(max_error < 1e-3, "fmm max relative error {}", max_error);
+        }
+    }
+}
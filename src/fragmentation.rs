@@ -0,0 +1,140 @@
+use rand::Rng;
+
+use crate::particle::Particle;
+use crate::vector::Vec2;
+
+// heaviest fragment is at most this many times the lightest one
+pub const FRAGMENT_MASS_RANGE: f32 = 10.0;
+
+// catastrophic disruption of a colliding pair once the specific impact energy
+// (0.5 * reduced mass * v^2 / total mass) goes over the threshold
+#[derive(Clone)]
+pub struct Fragmentation {
+    pub specific_energy: f32, // 0 turns it off
+    pub n_fragments: u32,
+    // fragment masses follow dN/dm ~ m^-size_exponent
+    pub size_exponent: f32,
+    // on top of the pair's center of mass velocity
+    pub ejection_velocity: f32,
+    // pairs that would make fragments lighter than this just bounce
+    pub min_mass: f32,
+}
+
+impl Fragmentation {
+    pub fn new(
+        specific_energy: f32,
+        n_fragments: u32,
+        size_exponent: f32,
+        ejection_velocity: f32,
+    ) -> Fragmentation {
+        return Fragmentation {
+            specific_energy,
+            n_fragments: n_fragments.max(2),
+            size_exponent,
+            ejection_velocity,
+            min_mass: 0.0,
+        };
+    }
+
+    pub fn disabled() -> Fragmentation {
+        return Fragmentation::new(0.0, 2, 1.5, 0.0);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        return self.specific_energy > 0.0;
+    }
+
+    // rel_vel is per unit time
+    pub fn is_disrupting(&self, pt1: &Particle, pt2: &Particle, rel_vel: Vec2) -> bool {
+        if !self.is_enabled() {
+            return false;
+        }
+
+        let mass = pt1.mass + pt2.mass;
+        if mass / (self.n_fragments as f32) < self.min_mass {
+            return false;
+        }
+
+        let reduced_mass = pt1.mass * pt2.mass / mass;
+        let specific_energy = 0.5 * reduced_mass * rel_vel.length_squared() / mass;
+
+        return specific_energy > self.specific_energy;
+    }
+
+    // inverse transform sample of the power law between 1 and FRAGMENT_MASS_RANGE
+    fn sample_mass_weight(&self) -> f32 {
+        let u: f32 = rand::rng().random_range(0.0..1.0);
+
+        let power = 1.0 - self.size_exponent;
+        if power.abs() < 1e-6 {
+            return FRAGMENT_MASS_RANGE.powf(u);
+        }
+
+        let upper = FRAGMENT_MASS_RANGE.powf(power);
+        return ((upper - 1.0) * u + 1.0).powf(1.0 / power);
+    }
+
+    pub fn fragment(&self, pt1: &Particle, pt2: &Particle) -> Vec<Particle> {
+        let mut rng = rand::rng();
+
+        let mass = pt1.mass + pt2.mass;
+        let center = (pt1.pos * pt1.mass + pt2.pos * pt2.mass) / mass;
+        let vel = (pt1.get_vel() * pt1.mass + pt2.get_vel() * pt2.mass) / mass;
+        let density = mass / (pt1.mass / pt1.get_density() + pt2.mass / pt2.get_density());
+
+        let mut masses: Vec<f32> = (0..self.n_fragments)
+            .map(|_| self.sample_mass_weight())
+            .collect();
+        let weight_sum: f32 = masses.iter().sum();
+        for fragment_mass in &mut masses {
+            *fragment_mass *= mass / weight_sum;
+        }
+
+        let mut fragments = Vec::new();
+        // fragments are made of whatever the bigger parent was
+        let parent = if pt1.mass >= pt2.mass { pt1 } else { pt2 };
+
+        let mut max_radius: f32 = 0.0;
+        for fragment_mass in &masses {
+            let mut fragment = Particle::new();
+            fragment.species = parent.species;
+            fragment.softening = parent.softening;
+            fragment.is_gas = parent.is_gas;
+            fragment.internal_energy = parent.internal_energy;
+            fragment.is_compact = parent.is_compact;
+            fragment.mass = *fragment_mass;
+            fragment.set_radius_from_density(density);
+            max_radius = max_radius.max(fragment.radius);
+            fragments.push(fragment);
+        }
+
+        // spread on a ring wide enough that neighbours do not overlap
+        let n_fragments = fragments.len() as f32;
+        let ring_radius = max_radius / (std::f32::consts::PI / n_fragments).sin();
+        let angle_offset: f32 = rng.random_range(0.0..std::f32::consts::TAU);
+
+        let mut offsets = Vec::new();
+        let mut kicks = Vec::new();
+        let mut mean_offset = Vec2::zero();
+        let mut mean_kick = Vec2::zero();
+        for (fragment_i, fragment) in fragments.iter().enumerate() {
+            let angle = angle_offset + std::f32::consts::TAU * fragment_i as f32 / n_fragments;
+            let dir = Vec2::new(angle.cos(), angle.sin());
+
+            let kick = dir * (self.ejection_velocity * rng.random_range(0.5..1.5));
+
+            mean_offset += dir * (ring_radius * fragment.mass / mass);
+            mean_kick += kick * (fragment.mass / mass);
+            offsets.push(dir * ring_radius);
+            kicks.push(kick);
+        }
+
+        // take out the mass weighted means so the center of mass and momentum stay put
+        for (fragment_i, fragment) in fragments.iter_mut().enumerate() {
+            fragment.set_pos(center + offsets[fragment_i] - mean_offset);
+            fragment.set_vel(vel + kicks[fragment_i] - mean_kick);
+        }
+
+        return fragments;
+    }
+}
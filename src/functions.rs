@@ -59,3 +59,23 @@ pub fn morton_key(pos: Vec2) -> u32 {
     let iy = quantize(pos.y, -1.0, 1.0, bits);
     morton2D(ix, iy)
 }
+
+// complementary error function, numerical recipes erfcc, relative error below 1.2e-7
+pub fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = -z * z - 1.26551223
+        + t * (1.00002368
+            + t * (0.37409196
+                + t * (0.09678418
+                    + t * (-0.18628806
+                        + t * (0.27886807
+                            + t * (-1.13520398
+                                + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277))))))));
+    let result = t * poly.exp();
+
+    if x >= 0.0 {
+        return result;
+    }
+    return 2.0 - result;
+}
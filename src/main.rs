@@ -1,10 +1,28 @@
+mod boundary;
+mod complex;
 mod constants;
+mod cosmology;
+mod diagnostics;
+mod drag;
+mod events;
+mod external;
+mod fft;
+mod fmm;
+mod fragmentation;
 mod functions;
 mod particle;
 mod particle_container;
+mod pm;
+mod pn;
 mod progress_bar;
 mod qtree;
 mod simulation;
+mod sink;
+mod size_distribution;
+mod softening;
+mod species;
+mod sph;
+mod timestep;
 mod vector;
 
 use std::io::Sink;
@@ -28,12 +46,28 @@ fn main() {
     simulation_specs.set_n_collision_steps(8);
     simulation_specs.set_update_cache_steps(4);
 
-    simulation_specs.set_n_sub_steps(32);
-
     let mut simulation = Simulation::construct(&simulation_specs);
 
     simulation.run();
     simulation
         .sim_recorder
         .export_recording("simulations/simulation_output.txt");
+    simulation
+        .sim_recorder
+        .export_tracers("simulations/simulation_tracers.txt");
+    simulation
+        .sim_recorder
+        .export_diagnostics("simulations/simulation_diagnostics.txt");
+    simulation
+        .sim_recorder
+        .export_merge_events("simulations/simulation_merges.txt");
+    simulation
+        .sim_recorder
+        .export_fragment_events("simulations/simulation_fragments.txt");
+    simulation
+        .sim_recorder
+        .export_accretion_events("simulations/simulation_accretion.txt");
+    simulation
+        .sim_recorder
+        .export_species("simulations/simulation_species.txt");
 }
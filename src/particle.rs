@@ -1,31 +1,111 @@
-use crate::constants::*;
 use crate::qtree::Bound;
 use crate::vector::Vec2;
 use std::fmt;
 
 pub struct Particle {
+    pub id: u32,
+    // where the particle started the current sub step
     pub prev_pos: Vec2,
     pub pos: Vec2,
+    pub vel: Vec2,
+    pub prev_vel: Vec2,
     pub prev_acc: Vec2,
     pub acc: Vec2,
+    // only filled in for the hermite integrator
+    pub prev_jerk: Vec2,
+    pub jerk: Vec2,
     pub mass: f32,
     pub radius: f32,
+    pub angle: f32,
+    pub angular_vel: f32,
+    pub inertia: f32,
+    // gravitational softening length, 0 picks up the global one when the particle is added
+    pub softening: f32,
+    // index into the species list of the specs
+    pub species: u32,
     pub n_collisions: u32,
     pub n_total_collisions: u32,
+    // block timesteps, inactive particles keep their acc until their step ends
+    pub time_bin: u32,
+    pub is_active: bool,
+    // merged away this step, dropped once the collision pass is done
+    pub is_consumed: bool,
+
+    // sph, only used for gas. internal_energy is per unit mass
+    pub is_gas: bool,
+    pub smoothing_length: f32,
+    pub gas_density: f32,
+    pub pressure: f32,
+    pub sound_speed: f32,
+    // fastest the viscosity lets information travel to this particle, for its timestep
+    pub signal_speed: f32,
+    pub internal_energy: f32,
+    pub du_dt: f32,
+
+    // gets the post newtonian terms against the other compact particles
+    pub is_compact: bool,
+
+    // swallows whatever comes within accretion_radius and stays bound to it
+    pub is_sink: bool,
+    pub accretion_radius: f32,
+
+    // feels gravity but leaves no mass in the tree or the mesh, mass is only for collisions
+    pub is_tracer: bool,
 }
 
 impl Particle {
     pub fn new() -> Particle {
-        return Particle {
+        let mut particle = Particle {
+            id: 0,
             prev_pos: Vec2::zero(),
             pos: Vec2::zero(),
+            vel: Vec2::zero(),
+            prev_vel: Vec2::zero(),
             prev_acc: Vec2::zero(),
             acc: Vec2::zero(),
+            prev_jerk: Vec2::zero(),
+            jerk: Vec2::zero(),
             mass: 1.0,
             radius: 0.0001,
+            angle: 0.0,
+            angular_vel: 0.0,
+            inertia: 0.0,
+            softening: 0.0,
+            species: 0,
             n_collisions: 0,
             n_total_collisions: 0,
+            time_bin: 0,
+            is_active: true,
+            is_consumed: false,
+            is_gas: false,
+            smoothing_length: 0.0,
+            gas_density: 0.0,
+            pressure: 0.0,
+            sound_speed: 0.0,
+            signal_speed: 0.0,
+            internal_energy: 0.0,
+            du_dt: 0.0,
+            is_compact: false,
+            is_sink: false,
+            accretion_radius: 0.0,
+            is_tracer: false,
         };
+        particle.update_inertia();
+
+        return particle;
+    }
+
+    // solid disk
+    pub fn update_inertia(&mut self) {
+        self.inertia = 0.5 * self.mass * self.radius * self.radius;
+    }
+
+    // what it weighs as a source of gravity
+    pub fn get_source_mass(&self) -> f32 {
+        if self.is_tracer {
+            return 0.0;
+        }
+        return self.mass;
     }
 
     pub fn reset_collisions(&mut self) {
@@ -36,34 +116,94 @@ impl Particle {
     pub fn reset_acc(&mut self) {
         self.prev_acc = self.acc;
         self.acc = Vec2::zero();
+        self.prev_jerk = self.jerk;
+        self.jerk = Vec2::zero();
+        self.du_dt = 0.0;
+    }
+
+    pub fn start_step(&mut self) {
+        self.prev_pos = self.pos;
+        self.prev_vel = self.vel;
     }
 
     pub fn set_density(&mut self, density: f32) {
         self.mass = self.radius * self.radius * self.radius * self.radius * density;
+        self.update_inertia();
+    }
+
+    // same (odd) convention as set_density
+    pub fn get_density(&self) -> f32 {
+        return self.mass / (self.radius * self.radius * self.radius * self.radius);
     }
 
-    pub fn integrate(&mut self, dt: f32) {
-        let mut vel = (self.pos - self.prev_pos);
+    pub fn set_radius_from_density(&mut self, density: f32) {
+        self.radius = (self.mass / density).powf(0.25);
+        self.update_inertia();
+    }
+
+    // position verlet, written as a kick followed by a drift. this is the same as
+    // pos + (pos - prev_pos) + acc * dt * dt without the velocity being implicit
+    pub fn integrate(&mut self, dt: f32, pattern_speed: f32) {
+        self.kick_rotating(dt, pattern_speed);
+        self.drift(dt);
+    }
+
+    pub fn kick(&mut self, dt: f32) {
+        self.vel += self.acc * dt;
+        self.internal_energy = (self.internal_energy + self.du_dt * dt).max(0.0);
+    }
 
-        if vel.length_squared() > MAX_SPEED_SQUARED {
-            vel = vel.normalize() * MAX_SPEED;
+    // kick in a frame rotating counter clockwise at pattern_speed. acc already has the
+    // centrifugal part, coriolis only turns the velocity so it is done exactly between two
+    // half kicks, which keeps the kick time symmetric
+    pub fn kick_rotating(&mut self, dt: f32, pattern_speed: f32) {
+        if pattern_speed == 0.0 {
+            self.kick(dt);
+            return;
         }
 
-        let new_pos = self.pos + vel + self.acc * (dt * dt);
-        self.prev_pos = self.pos;
-        self.pos = new_pos;
-        self.reset_acc();
+        self.kick(dt * 0.5);
+        self.vel = self.vel.rotate(-2.0 * pattern_speed * dt);
+        self.kick(dt * 0.5);
+    }
+
+    pub fn drift(&mut self, dt: f32) {
+        self.pos += self.vel * dt;
+        self.angle += self.angular_vel * dt;
+    }
+
+    // taylor expansion from the start of the step using acc and jerk
+    pub fn hermite_predict(&mut self, dt: f32) {
+        let dt2 = dt * dt;
+
+        self.pos = self.prev_pos
+            + self.prev_vel * dt
+            + self.acc * (dt2 * 0.5)
+            + self.jerk * (dt2 * dt / 6.0);
+        self.vel = self.prev_vel + self.acc * dt + self.jerk * (dt2 * 0.5);
+        self.angle += self.angular_vel * dt;
+    }
+
+    // needs acc and jerk at the predicted state, the start of step ones are in prev_acc/prev_jerk
+    pub fn hermite_correct(&mut self, dt: f32) {
+        let dt2 = dt * dt;
+
+        let new_vel = self.prev_vel
+            + (self.prev_acc + self.acc) * (dt * 0.5)
+            + (self.prev_jerk - self.jerk) * (dt2 / 12.0);
+        self.pos = self.prev_pos
+            + (self.prev_vel + new_vel) * (dt * 0.5)
+            + (self.prev_acc - self.acc) * (dt2 / 12.0);
+        self.vel = new_vel;
+        self.internal_energy = (self.internal_energy + self.du_dt * dt).max(0.0);
     }
 
     pub fn get_vel(&self) -> Vec2 {
-        return self.pos - self.prev_pos;
+        return self.vel;
     }
 
     pub fn get_speed(&self) -> f32 {
-        let dx = self.pos.x - self.prev_pos.x;
-        let dy = self.pos.y - self.prev_pos.y;
-
-        return (dx * dx + dy * dy).sqrt();
+        return self.vel.length();
     }
 
     pub fn set_pos(&mut self, new_pos: Vec2) {
@@ -71,14 +211,21 @@ impl Particle {
         self.prev_pos = new_pos;
     }
 
+    // moves the particle without changing its velocity
+    pub fn shift_pos(&mut self, delta: Vec2) {
+        self.pos += delta;
+    }
+
     pub fn set_vel(&mut self, new_vel: Vec2) {
-        self.prev_pos = self.pos - new_vel;
+        self.vel = new_vel;
     }
 
     pub fn apply_force(&mut self, force: Vec2) {
         self.acc += force / self.mass;
     }
 
+    // own disk plus room to move before the collision cache is refreshed, the size of the
+    // other particle is left to QuadTree::idx_contacts
     pub fn get_bound(&self) -> Bound {
         let offset = Vec2::new(self.radius, self.radius) * 4.0;
 
@@ -108,6 +255,12 @@ pub struct ParticleData {
     pub position: Vec2,
     pub speed: f32,
     pub n_collisions: u32,
+    pub spin: f32,
+    pub species: u32,
+    pub gas_density: f32,
+    pub pressure: f32,
+    // filled in by the recorder, it needs the equation of state
+    pub temperature: f32,
 }
 
 impl ParticleData {
@@ -116,6 +269,28 @@ impl ParticleData {
             position: particle.pos,
             speed: particle.get_speed(),
             n_collisions: particle.n_total_collisions,
+            spin: particle.angular_vel,
+            species: particle.species,
+            gas_density: particle.gas_density,
+            pressure: particle.pressure,
+            temperature: 0.0,
+        };
+    }
+
+    // for a particle in a frame that has turned pattern_speed * time since the start
+    pub fn inertial(particle: &Particle, pattern_speed: f32, time: f32) -> ParticleData {
+        let angle = pattern_speed * time;
+        let vel = particle.vel + particle.pos.perp() * pattern_speed;
+
+        return ParticleData {
+            position: particle.pos.rotate(angle),
+            speed: vel.length(),
+            n_collisions: particle.n_total_collisions,
+            spin: particle.angular_vel + pattern_speed,
+            species: particle.species,
+            gas_density: particle.gas_density,
+            pressure: particle.pressure,
+            temperature: 0.0,
         };
     }
 }
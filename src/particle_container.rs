@@ -2,18 +2,81 @@ use std::thread::spawn;
 
 use rand::random;
 
+use crate::boundary::{Boundary, WallMaterial};
 use crate::constants::*;
+use crate::cosmology::{Cosmology, wrap};
+use crate::diagnostics::Diagnostics;
+use crate::drag::BackgroundFlow;
+use crate::events::{AccretionEvent, FragmentEvent, MergeEvent};
+use crate::external::ExternalPotential;
+use crate::fmm::Fmm;
+use crate::fragmentation::Fragmentation;
 use crate::functions::*;
 use crate::particle::*;
-use crate::qtree::QuadTree;
-use crate::simulation::NORMAL_DISTRIBUTION;
+use crate::pm::ParticleMesh;
+use crate::pn::PostNewtonian;
+use crate::qtree::{Bound, QuadTree};
 use crate::simulation::SimulationSpecs;
+use crate::simulation::TREE_PM_GRAVITY;
+use crate::simulation::{NORMAL_DISTRIBUTION, ZELDOVICH_DISTRIBUTION};
+use crate::sink::{SinkFormation, accretion_energy};
+use crate::softening::{PLUMMER_SOFTENING, Softening};
+use crate::species::{InteractionMatrix, Species};
+use crate::sph::Sph;
+use crate::timestep::*;
 use crate::vector::*;
 
 pub struct Container {
     pub particles: Vec<Particle>,
     pub cached_potential_collisions: Vec<Vec<usize>>,
     pub quadtree: QuadTree,
+    pub fmm: Fmm,
+    pub mesh: ParticleMesh,
+    pub softening: Softening,
+    pub boundary: Boundary,
+    pub diagnostics: Diagnostics,
+    pub time: f32,
+    pub restitution: f32,
+    pub friction: f32,
+    pub next_id: u32,
+
+    // merging, a merge velocity of 0 turns it off
+    pub merge_velocity: f32,
+    pub merge_density: f32,
+    pub is_merge_conserving_spin: bool,
+    pub pending_merges: Vec<(usize, usize)>,
+    pub merge_events: Vec<MergeEvent>,
+
+    pub fragmentation: Fragmentation,
+    pub pending_fragmentations: Vec<(usize, usize)>,
+    pub fragment_events: Vec<FragmentEvent>,
+
+    pub external_potentials: Vec<ExternalPotential>,
+    // angular velocity of the frame around the origin, 0 is an inertial frame
+    pub pattern_speed: f32,
+
+    pub species: Vec<Species>,
+    pub gravity_matrix: InteractionMatrix,
+    pub collision_matrix: InteractionMatrix,
+    // species that go into the quadtree and the mesh, everything but a single species while
+    // gravity is done one source species at a time
+    pub gravity_sources: u32,
+
+    pub sph: Sph,
+    pub background_flow: BackgroundFlow,
+
+    pub cosmology: Cosmology,
+    // 1 without a cosmology
+    pub scale_factor: f32,
+    pub prev_scale_factor: f32,
+
+    pub post_newtonian: PostNewtonian,
+    // how far the velocities trail the positions during a force pass, half a step after the
+    // first kick of a leapfrog. divided by 2^time_bin for block steps
+    pub velocity_lag: f32,
+
+    pub sink_formation: SinkFormation,
+    pub accretion_events: Vec<AccretionEvent>,
 }
 
 impl Container {
@@ -22,51 +85,226 @@ impl Container {
             particles: Vec::new(),
             cached_potential_collisions: Vec::new(),
             quadtree: QuadTree::new(),
+            fmm: Fmm::new(FMM_ORDER as usize, FMM_THETA),
+            mesh: ParticleMesh::new(PM_GRID as usize, false, 0.0),
+            softening: Softening::new(PLUMMER_SOFTENING, EPS),
+            boundary: Boundary::square(1.0, WallMaterial::new(WALL_RESTITUTION, WALL_FRICTION)),
+            diagnostics: Diagnostics::new(),
+            time: 0.0,
+            restitution: COLLISION_RESTITUTION,
+            friction: COLLISION_FRICTION,
+            next_id: 0,
+            merge_velocity: 0.0,
+            merge_density: 0.0,
+            is_merge_conserving_spin: true,
+            pending_merges: Vec::new(),
+            merge_events: Vec::new(),
+            fragmentation: Fragmentation::disabled(),
+            pending_fragmentations: Vec::new(),
+            fragment_events: Vec::new(),
+            external_potentials: Vec::new(),
+            pattern_speed: 0.0,
+            species: vec![Species::default()],
+            gravity_matrix: InteractionMatrix::new(),
+            collision_matrix: InteractionMatrix::new(),
+            gravity_sources: u32::MAX,
+            sph: Sph::adiabatic(SPH_GAMMA),
+            background_flow: BackgroundFlow::uniform(Vec2::zero()),
+            cosmology: Cosmology::disabled(),
+            scale_factor: 1.0,
+            prev_scale_factor: 1.0,
+            post_newtonian: PostNewtonian::disabled(),
+            velocity_lag: 0.0,
+            sink_formation: SinkFormation::disabled(),
+            accretion_events: Vec::new(),
         };
     }
 
+    pub fn load_specs(&mut self, info: &SimulationSpecs) {
+        self.boundary = info.get_boundary().clone();
+        self.restitution = info.get_collision_restitution();
+        self.friction = info.get_collision_friction();
+        self.merge_velocity = info.get_merge_velocity();
+        self.merge_density = info.get_merge_density();
+        self.is_merge_conserving_spin = info.is_merge_conserving_spin();
+        self.fragmentation = info.get_fragmentation().clone();
+        self.external_potentials = info.get_external_potentials().clone();
+        self.pattern_speed = info.get_pattern_speed();
+        self.species = info.get_species().clone();
+        self.gravity_matrix = info.get_gravity_matrix().clone();
+        self.collision_matrix = info.get_collision_matrix().clone();
+        self.sph = info.get_sph().clone();
+        self.background_flow = info.get_background_flow();
+        self.cosmology = info.get_cosmology();
+        self.post_newtonian = info.get_post_newtonian();
+        self.sink_formation = info.get_sink_formation();
+        if self.cosmology.is_enabled() {
+            self.scale_factor = self.cosmology.get_start_scale_factor();
+            self.prev_scale_factor = self.scale_factor;
+        }
+        self.quadtree.set_theta(info.get_theta());
+        self.quadtree.set_quadrupole(info.is_using_quadrupole());
+        self.fmm.set_order(info.get_fmm_order() as usize);
+        self.fmm.set_theta(info.get_fmm_theta());
+        self.softening = info.get_softening();
+        self.quadtree.set_softening(self.softening);
+        self.fmm.set_softening(self.softening);
+        self.mesh.set_grid(info.get_pm_grid() as usize);
+        self.mesh.set_periodic(info.is_pm_periodic());
+        self.mesh.set_columns(self.cosmology.is_enabled());
+        if info.get_gravity_solver() == TREE_PM_GRAVITY {
+            self.mesh.set_split_scale(info.get_pm_split_scale());
+        } else {
+            self.mesh.set_split_scale(0.0);
+        }
+    }
+
+    // every particle has to come in through here so ids and caches stay in sync
+    pub fn push_particle(&mut self, mut particle: Particle) -> u32 {
+        particle.id = self.next_id;
+        self.next_id += 1;
+
+        if particle.softening == 0.0 {
+            particle.softening = self.softening.get_length();
+        }
+
+        self.particles.push(particle);
+        self.cached_potential_collisions.push(Vec::new());
+
+        return self.next_id - 1;
+    }
+
     pub fn construct_quadtree(&mut self) {
         self.quadtree.reset();
 
         for particle_i in 0..self.particles.len() {
+            if self.gravity_sources & (1 << self.particles[particle_i].species) == 0 {
+                continue;
+            }
             self.quadtree.add_particle(&self.particles, particle_i);
         }
     }
 
-    pub fn init_particles(&mut self, info: &SimulationSpecs) {
-        let spawn_radius = info.get_spawn_radius();
-        let spawn_radius_squared = info.get_spawn_radius_squared();
+    pub fn rebuild_quadtree(&mut self) {
+        self.construct_quadtree();
+        self.quadtree.propogate_mass();
+    }
 
-        for _particle_i in 0..info.get_n_particles() {
-            let mut new_particle = Particle::new();
+    // rebuilds the quadtree with only the source species, u32::MAX goes back to all of them
+    pub fn set_gravity_sources(&mut self, source_mask: u32) {
+        self.gravity_sources = source_mask;
+        self.rebuild_quadtree();
+    }
 
-            let mut random_pos = Vec2::zero();
-            let distribution = info.get_distribution();
-            if distribution == NORMAL_DISTRIBUTION {
-                random_pos = Vec2::rand_normal() * spawn_radius;
-            } else {
-                random_pos = Vec2::rand_uniform() * spawn_radius;
+    pub fn is_gravity_all_to_all(&self) -> bool {
+        return self.gravity_matrix.is_all(self.species.len());
+    }
+
+    pub fn has_particles(&self, species_i: u32) -> bool {
+        return self
+            .particles
+            .iter()
+            .any(|particle| particle.species == species_i);
+    }
+
+    // true if any species feels gravity from this one
+    pub fn is_gravity_source(&self, species_i: u32) -> bool {
+        for target_i in 0..self.species.len() {
+            if self.gravity_matrix.get(target_i as u32, species_i) {
+                return true;
             }
+        }
+        return false;
+    }
 
-            while random_pos.length_squared() > spawn_radius_squared {
-                if distribution == NORMAL_DISTRIBUTION {
-                    random_pos = Vec2::rand_normal() * spawn_radius;
-                } else {
-                    random_pos = Vec2::rand_uniform() * spawn_radius;
+    // adaptive softening follows the local density, every particle gets a length from the
+    // distance to its n-th neighbour. leaves the quadtree built with the new lengths
+    pub fn update_adaptive_softening(&mut self) {
+        if !self.softening.is_adaptive() {
+            return;
+        }
+        self.rebuild_quadtree();
+
+        let n_neighbours = self.softening.get_n_neighbours() as usize;
+        let mut candidates = Vec::new();
+
+        for particle_i in 0..self.particles.len() {
+            let distance = self.quadtree.get_neighbour_distance(
+                &self.particles,
+                self.particles[particle_i].pos,
+                n_neighbours,
+                &mut candidates,
+            );
+            self.particles[particle_i].softening = self.softening.adaptive_length(distance);
+        }
+
+        self.quadtree.refit(&self.particles);
+    }
+
+    pub fn init_particles(&mut self, info: &SimulationSpecs) {
+        self.load_specs(info);
+
+        let is_zeldovich = info.get_distribution() == ZELDOVICH_DISTRIBUTION;
+        for species_i in 0..self.species.len() {
+            if species_i == 0 && is_zeldovich {
+                for new_particle in self.cosmology.zeldovich(&self.species[0], 0) {
+                    self.push_particle(new_particle);
                 }
+                continue;
+            }
+            for _particle_i in 0..info.get_species_count(species_i) {
+                let new_particle = self.species[species_i].make_particle(species_i as u32);
+                self.spawn_particle(info, new_particle);
             }
-
-            new_particle.set_pos(random_pos);
-            self.particles.push(new_particle);
-            self.cached_potential_collisions.push(Vec::new());
         }
 
-        self.construct_quadtree();
+        // overlapping spawns are not real impacts so nothing merges while they are pushed apart
+        let merge_velocity = self.merge_velocity;
+        self.merge_velocity = 0.0;
+
+        self.rebuild_quadtree();
         self.particle_collision(5, 1, 1.0);
+        self.merge_velocity = merge_velocity;
+        self.container_collisions(1.0);
+        // and pushing them out of the walls is not a wall hit either. a plain reset would keep
+        // the energy in the running total
+        self.diagnostics = Diagnostics::new();
+
+        // the zel'dovich particles come with their velocities
+        if is_zeldovich {
+            return;
+        }
 
+        // same spin up as the old 0.0001 displacement per sub step
+        let spin_up = 0.0001 / info.get_sub_step_dt();
         for particle in &mut self.particles {
-            particle.set_vel(particle.pos.perp() * 0.0001);
+            particle.set_vel(particle.pos.perp() * spin_up);
+        }
+    }
+
+    // somewhere random inside the spawn radius
+    fn spawn_particle(&mut self, info: &SimulationSpecs, mut new_particle: Particle) {
+        let spawn_radius = info.get_spawn_radius();
+        let spawn_radius_squared = info.get_spawn_radius_squared();
+
+        let mut random_pos = Vec2::zero();
+        let distribution = info.get_distribution();
+        if distribution == NORMAL_DISTRIBUTION {
+            random_pos = Vec2::rand_normal() * spawn_radius;
+        } else {
+            random_pos = Vec2::rand_uniform() * spawn_radius;
+        }
+
+        while random_pos.length_squared() > spawn_radius_squared {
+            if distribution == NORMAL_DISTRIBUTION {
+                random_pos = Vec2::rand_normal() * spawn_radius;
+            } else {
+                random_pos = Vec2::rand_uniform() * spawn_radius;
+            }
         }
+
+        new_particle.set_pos(random_pos);
+        self.push_particle(new_particle);
     }
 
     pub fn add_particle(&mut self) {
@@ -85,25 +323,80 @@ impl Container {
 
         new_particle.set_vel(random_pos.perp() * 0.00003);
         new_particle.set_density(1.0);
-        self.particles.push(new_particle);
-        self.cached_potential_collisions.push(Vec::new());
+        self.push_particle(new_particle);
     }
 
     pub fn interparticle_gravity_quadratic(&mut self) {
         let n_particles = self.particles.len();
         for pt1_i in 0..n_particles {
             for pt2_i in (pt1_i + 1)..n_particles {
+                let species1 = self.particles[pt1_i].species;
+                let species2 = self.particles[pt2_i].species;
+                let is_pulling_1 =
+                    self.gravity_matrix.get(species1, species2) && !self.particles[pt2_i].is_tracer;
+                let is_pulling_2 =
+                    self.gravity_matrix.get(species2, species1) && !self.particles[pt1_i].is_tracer;
+
                 let delta = self.particles[pt2_i].pos - self.particles[pt1_i].pos;
                 let delta_length_squared = delta.length_squared();
 
                 if delta_length_squared != 0.0 {
-                    let attract_vec = delta.normalize() / (delta_length_squared)
+                    // symmetric so the pair forces still cancel
+                    let length = self.particles[pt1_i]
+                        .softening
+                        .max(self.particles[pt2_i].softening);
+                    let attract_vec = delta
+                        * self.softening.force_factor(delta_length_squared, length)
                         * GRAVITY_CONST
                         * self.particles[pt1_i].mass
                         * self.particles[pt2_i].mass;
 
-                    self.particles[pt1_i].apply_force(attract_vec);
-                    self.particles[pt2_i].apply_force(-attract_vec);
+                    if self.particles[pt1_i].is_active && is_pulling_1 {
+                        self.particles[pt1_i].apply_force(attract_vec);
+                    }
+                    if self.particles[pt2_i].is_active && is_pulling_2 {
+                        self.particles[pt2_i].apply_force(-attract_vec);
+                    }
+                }
+            }
+        }
+    }
+
+    // only the time derivative of the quadratic gravity above, the hermite integrator wants both
+    pub fn interparticle_jerk_quadratic(&mut self) {
+        let n_particles = self.particles.len();
+        for pt1_i in 0..n_particles {
+            for pt2_i in (pt1_i + 1)..n_particles {
+                let delta = self.particles[pt2_i].pos - self.particles[pt1_i].pos;
+                let delta_vel = self.particles[pt2_i].vel - self.particles[pt1_i].vel;
+                let delta_length_squared = delta.length_squared();
+
+                if delta_length_squared != 0.0 {
+                    let length = self.particles[pt1_i]
+                        .softening
+                        .max(self.particles[pt2_i].softening);
+                    let force_factor = self.softening.force_factor(delta_length_squared, length);
+                    let jerk_factor = self.softening.jerk_factor(delta_length_squared, length);
+
+                    let jerk_vec = (delta_vel * force_factor
+                        + delta * (jerk_factor * delta.dot(delta_vel)))
+                        * GRAVITY_CONST;
+
+                    let mass1 = self.particles[pt1_i].get_source_mass();
+                    let mass2 = self.particles[pt2_i].get_source_mass();
+
+                    if self
+                        .gravity_matrix
+                        .get(self.particles[pt1_i].species, self.particles[pt2_i].species)
+                    {
+                        self.particles[pt1_i].jerk += jerk_vec * mass2;
+                    }
+                    if self
+                        .gravity_matrix
+                        .get(self.particles[pt2_i].species, self.particles[pt1_i].species)
+                    {
+                        self.particles[pt2_i].jerk -= jerk_vec * mass1;
+                    }
                 }
             }
         }
@@ -111,14 +404,105 @@ impl Container {
 
     pub fn interparticle_gravity(&mut self) {
         for particle in &mut self.particles {
-            let grav_force = self.quadtree.get_grav_force(particle.pos) * GRAVITY_CONST;
+            if !particle.is_active
+                || !self
+                    .gravity_matrix
+                    .any(particle.species, self.gravity_sources)
+            {
+                continue;
+            }
+
+            let grav_force = self
+                .quadtree
+                .get_grav_force(particle.pos, particle.softening)
+                * (GRAVITY_CONST * particle.mass);
             // println!("{}", grav_force);
 
             particle.apply_force(grav_force);
         }
     }
 
+    // needs the same built quadtree as interparticle_gravity
+    pub fn interparticle_gravity_fmm(&mut self) {
+        self.fmm.compute(&self.quadtree);
+
+        for particle in &mut self.particles {
+            if !particle.is_active
+                || !self
+                    .gravity_matrix
+                    .any(particle.species, self.gravity_sources)
+            {
+                continue;
+            }
+
+            let grav_force =
+                self.fmm
+                    .get_grav_force(&self.quadtree, particle.pos, particle.softening)
+                    * (GRAVITY_CONST * particle.mass);
+
+            particle.apply_force(grav_force);
+        }
+    }
+
+    pub fn interparticle_gravity_pm(&mut self) {
+        self.mesh.compute(&self.particles, self.gravity_sources);
+
+        for particle in &mut self.particles {
+            if !particle.is_active
+                || !self
+                    .gravity_matrix
+                    .any(particle.species, self.gravity_sources)
+            {
+                continue;
+            }
+
+            let grav_force =
+                self.mesh.get_grav_force(particle.pos) * (GRAVITY_CONST * particle.mass);
+            particle.apply_force(grav_force);
+        }
+    }
+
+    // the mesh has to be set up with a split scale, needs the same built quadtree as
+    // interparticle_gravity
+    pub fn interparticle_gravity_tree_pm(&mut self) {
+        self.mesh.compute(&self.particles, self.gravity_sources);
+        let split_radius = self.mesh.get_split_radius();
+
+        for particle in &mut self.particles {
+            if !particle.is_active
+                || !self
+                    .gravity_matrix
+                    .any(particle.species, self.gravity_sources)
+            {
+                continue;
+            }
+
+            let grav_force = (self.mesh.get_grav_force(particle.pos)
+                + self.quadtree.get_short_range_force(
+                    particle.pos,
+                    particle.softening,
+                    split_radius,
+                ))
+                * (GRAVITY_CONST * particle.mass);
+            particle.apply_force(grav_force);
+        }
+    }
+
     pub fn resolve_collision(&mut self, pt1_i: usize, pt2_i: usize) {
+        if self.particles[pt1_i].is_consumed || self.particles[pt2_i].is_consumed {
+            return;
+        }
+        if !self
+            .collision_matrix
+            .get(self.particles[pt1_i].species, self.particles[pt2_i].species)
+        {
+            return;
+        }
+        if !self.is_colliding(pt1_i) || !self.is_colliding(pt2_i) {
+            return;
+        }
+        let is_tracer_involved = self.particles[pt1_i].is_tracer || self.particles[pt2_i].is_tracer;
+
         let mut delta = self.particles[pt2_i].pos - self.particles[pt1_i].pos;
 
         let mut dist2 = delta.length_squared();
@@ -135,9 +519,68 @@ impl Container {
 
             let pen = min_dis - dist;
 
-            let corr = n * (pen * 0.5);
-            self.particles[pt1_i].pos -= corr;
-            self.particles[pt2_i].pos += corr;
+            let rel_vel = self.particles[pt2_i].get_vel() - self.particles[pt1_i].get_vel();
+            let is_merging = !is_tracer_involved
+                && rel_vel.length_squared() < self.merge_velocity * self.merge_velocity;
+            let is_disrupting = !is_tracer_involved
+                && rel_vel.dot(n) < 0.0
+                && self.fragmentation.is_disrupting(
+                    &self.particles[pt1_i],
+                    &self.particles[pt2_i],
+                    rel_vel,
+                );
+
+            // sinks never break up and keep their id, whatever would merge with or shatter
+            // one gets accreted instead so it stays in the accretion history
+            if (is_merging || is_disrupting)
+                && self.particles[pt1_i].is_sink != self.particles[pt2_i].is_sink
+            {
+                if self.particles[pt1_i].is_sink {
+                    self.absorb_into_sink(pt1_i, pt2_i);
+                } else {
+                    self.absorb_into_sink(pt2_i, pt1_i);
+                }
+                return;
+            }
+
+            // two sinks merge, merge_particles keeps them a sink
+            if is_merging || (is_disrupting && self.particles[pt1_i].is_sink) {
+                self.particles[pt1_i].is_consumed = true;
+                self.particles[pt2_i].is_consumed = true;
+                self.pending_merges.push((pt1_i, pt2_i));
+                return;
+            }
+
+            if is_disrupting {
+                self.particles[pt1_i].is_consumed = true;
+                self.particles[pt2_i].is_consumed = true;
+                self.pending_fragmentations.push((pt1_i, pt2_i));
+                return;
+            }
+
+            let (inv_mass1, inv_mass2) = self.contact_inv_masses(pt1_i, pt2_i);
+            let inv_mass_sum = inv_mass1 + inv_mass2;
+
+            // the lighter particle gets pushed further, this does not touch the velocities
+            let corr = n * (pen / inv_mass_sum);
+            self.particles[pt1_i].shift_pos(-corr * inv_mass1);
+            self.particles[pt2_i].shift_pos(corr * inv_mass2);
+
+            // only approaching particles get an impulse
+            let vel1 = self.particles[pt1_i].get_vel();
+            let vel2 = self.particles[pt2_i].get_vel();
+            let normal_vel = (vel2 - vel1).dot(n);
+
+            if normal_vel < 0.0 {
+                let impulse = -(1.0 + self.restitution) * normal_vel / inv_mass_sum;
+
+                self.particles[pt1_i].set_vel(vel1 - n * (impulse * inv_mass1));
+                self.particles[pt2_i].set_vel(vel2 + n * (impulse * inv_mass2));
+
+                if self.friction > 0.0 {
+                    self.apply_contact_friction(pt1_i, pt2_i, n, impulse);
+                }
+            }
 
             self.particles[pt1_i].n_collisions += 1;
             self.particles[pt2_i].n_collisions += 1;
@@ -146,6 +589,65 @@ impl Container {
         }
     }
 
+    // tracers only collide when their species says so
+    pub fn is_colliding(&self, particle_i: usize) -> bool {
+        let particle = &self.particles[particle_i];
+        return !particle.is_tracer || self.species[particle.species as usize].is_tracer_colliding;
+    }
+
+    // a tracer can not push a real particle around, that one acts like a wall for it
+    pub fn contact_inv_masses(&self, pt1_i: usize, pt2_i: usize) -> (f32, f32) {
+        let pt1 = &self.particles[pt1_i];
+        let pt2 = &self.particles[pt2_i];
+        if pt1.is_tracer && !pt2.is_tracer {
+            return (1.0 / pt1.mass, 0.0);
+        }
+        if pt2.is_tracer && !pt1.is_tracer {
+            return (0.0, 1.0 / pt2.mass);
+        }
+        return (1.0 / pt1.mass, 1.0 / pt2.mass);
+    }
+
+    // coulomb friction at the contact point, capped by the normal impulse. this is what
+    // gets the particles spinning
+    pub fn apply_contact_friction(
+        &mut self,
+        pt1_i: usize,
+        pt2_i: usize,
+        n: Vec2,
+        normal_impulse: f32,
+    ) {
+        let t = n.perp();
+
+        let radius1 = self.particles[pt1_i].radius;
+        let radius2 = self.particles[pt2_i].radius;
+        let (inv_mass1, inv_mass2) = self.contact_inv_masses(pt1_i, pt2_i);
+        // 0 for a real particle touching a tracer, like its inverse mass
+        let inv_inertia1 = inv_mass1 * self.particles[pt1_i].mass / self.particles[pt1_i].inertia;
+        let inv_inertia2 = inv_mass2 * self.particles[pt2_i].mass / self.particles[pt2_i].inertia;
+
+        let vel1 = self.particles[pt1_i].get_vel();
+        let vel2 = self.particles[pt2_i].get_vel();
+
+        // velocity of the contact point on 2 relative to the one on 1
+        let contact_vel1 = vel1 + t * (self.particles[pt1_i].angular_vel * radius1);
+        let contact_vel2 = vel2 - t * (self.particles[pt2_i].angular_vel * radius2);
+        let tangent_vel = (contact_vel2 - contact_vel1).dot(t);
+
+        let inv_eff_mass = inv_mass1
+            + inv_mass2
+            + radius1 * radius1 * inv_inertia1
+            + radius2 * radius2 * inv_inertia2;
+
+        let max_impulse = self.friction * normal_impulse;
+        let impulse = (-tangent_vel / inv_eff_mass).clamp(-max_impulse, max_impulse);
+
+        self.particles[pt1_i].set_vel(vel1 - t * (impulse * inv_mass1));
+        self.particles[pt2_i].set_vel(vel2 + t * (impulse * inv_mass2));
+        self.particles[pt1_i].angular_vel -= impulse * radius1 * inv_inertia1;
+        self.particles[pt2_i].angular_vel -= impulse * radius2 * inv_inertia2;
+    }
+
     pub fn particle_collision(
         &mut self,
         n_collision_steps: u32,
@@ -166,7 +668,7 @@ impl Container {
             if coll_step_i % n_update_cache_steps == 0 {
                 for pt1_i in &collision_particles_i {
                     self.cached_potential_collisions[*pt1_i].clear();
-                    self.quadtree.idx_bound(
+                    self.quadtree.idx_contacts(
                         &self.particles[*pt1_i].get_bound(),
                         &mut self.cached_potential_collisions[*pt1_i],
                     );
@@ -205,6 +707,284 @@ impl Container {
 
             collision_particles_i = new_collision_particles;
         }
+
+        self.apply_collision_events();
+    }
+
+    pub fn merge_particles(&mut self, pt1_i: usize, pt2_i: usize) -> Particle {
+        let pt1 = &self.particles[pt1_i];
+        let pt2 = &self.particles[pt2_i];
+
+        let mass = pt1.mass + pt2.mass;
+        let center = (pt1.pos * pt1.mass + pt2.pos * pt2.mass) / mass;
+        let vel = (pt1.get_vel() * pt1.mass + pt2.get_vel() * pt2.mass) / mass;
+
+        let density = if self.merge_density > 0.0 {
+            self.merge_density
+        } else {
+            // keeps the total volume of the parents
+            let volume1 = pt1.mass / pt1.get_density();
+            let volume2 = pt2.mass / pt2.get_density();
+            mass / (volume1 + volume2)
+        };
+
+        let mut spin_momentum = pt1.inertia * pt1.angular_vel + pt2.inertia * pt2.angular_vel;
+        if self.is_merge_conserving_spin {
+            // orbital part of the pair about its center of mass ends up as spin
+            spin_momentum += (pt1.pos - center).cross(pt1.get_vel() - vel) * pt1.mass;
+            spin_momentum += (pt2.pos - center).cross(pt2.get_vel() - vel) * pt2.mass;
+        }
+
+        let mut merged = Particle::new();
+        merged.mass = mass;
+        merged.set_radius_from_density(density);
+        merged.set_pos(center);
+        merged.set_vel(vel);
+        merged.angular_vel = spin_momentum / merged.inertia;
+        merged.n_total_collisions = pt1.n_total_collisions + pt2.n_total_collisions;
+        merged.softening = pt1.softening.max(pt2.softening);
+        merged.species = if pt1.mass >= pt2.mass {
+            pt1.species
+        } else {
+            pt2.species
+        };
+        // thermal energy is conserved too
+        merged.is_gas = pt1.is_gas || pt2.is_gas;
+        merged.internal_energy =
+            (pt1.internal_energy * pt1.mass + pt2.internal_energy * pt2.mass) / mass;
+        merged.is_compact = pt1.is_compact || pt2.is_compact;
+        merged.is_sink = pt1.is_sink || pt2.is_sink;
+        merged.accretion_radius = pt1.accretion_radius.max(pt2.accretion_radius);
+
+        return merged;
+    }
+
+    // merges and fragmentations are only queued during the collision pass since they
+    // change the particle indices. sinks swallow right away and only leave a consumed particle
+    pub fn apply_collision_events(&mut self) {
+        if self.pending_merges.is_empty()
+            && self.pending_fragmentations.is_empty()
+            && !self.particles.iter().any(|particle| particle.is_consumed)
+        {
+            return;
+        }
+
+        let pending_merges = std::mem::take(&mut self.pending_merges);
+        for (pt1_i, pt2_i) in pending_merges {
+            let merged = self.merge_particles(pt1_i, pt2_i);
+            let child_mass = merged.mass;
+            let parent_ids = [self.particles[pt1_i].id, self.particles[pt2_i].id];
+
+            let child_id = self.push_particle(merged);
+            self.merge_events.push(MergeEvent {
+                time: self.time,
+                parent_ids,
+                child_id,
+                child_mass,
+            });
+        }
+
+        let pending_fragmentations = std::mem::take(&mut self.pending_fragmentations);
+        for (pt1_i, pt2_i) in pending_fragmentations {
+            let fragments = self
+                .fragmentation
+                .fragment(&self.particles[pt1_i], &self.particles[pt2_i]);
+            let pt1 = &self.particles[pt1_i];
+            let pt2 = &self.particles[pt2_i];
+            let parent_ids = [pt1.id, pt2.id];
+            let total_mass = pt1.mass + pt2.mass;
+            let center = (pt1.pos * pt1.mass + pt2.pos * pt2.mass) / total_mass;
+
+            let mut child_ids = Vec::new();
+            for mut fragment in fragments {
+                // the ring can reach through a wall or out of the box. sweeping each fragment
+                // out from the collision point lets the walls put it back like any other particle
+                fragment.prev_pos = center;
+                self.boundary
+                    .resolve_particle(&mut fragment, &mut self.diagnostics);
+                if self.cosmology.is_enabled() {
+                    fragment.pos = wrap(fragment.pos);
+                }
+                fragment.set_pos(fragment.pos);
+
+                child_ids.push(self.push_particle(fragment));
+            }
+
+            self.fragment_events.push(FragmentEvent {
+                time: self.time,
+                parent_ids,
+                child_ids,
+                total_mass,
+            });
+        }
+
+        self.remove_consumed_particles();
+    }
+
+    pub fn remove_consumed_particles(&mut self) {
+        self.particles.retain(|particle| !particle.is_consumed);
+
+        self.cached_potential_collisions
+            .truncate(self.particles.len());
+        for cache in &mut self.cached_potential_collisions {
+            cache.clear();
+        }
+
+        // the tree still points at the old indices
+        self.rebuild_quadtree();
+    }
+
+    pub fn is_using_sinks(&self) -> bool {
+        return self.sink_formation.is_enabled() || self.particles.iter().any(|p| p.is_sink);
+    }
+
+    // densest first, so a clump turns into one sink and not every particle in it. anything
+    // already inside a sink is left for the accretion
+    pub fn form_sinks(&mut self) {
+        let threshold = self.sink_formation.density_threshold;
+        let n_neighbours = self.sink_formation.n_neighbours as usize;
+        let mut candidates = Vec::new();
+
+        let mut dense = Vec::new();
+        for particle_i in 0..self.particles.len() {
+            let particle = &self.particles[particle_i];
+            if particle.is_sink
+                || particle.is_consumed
+                || particle.is_tracer
+                || !self.sink_formation.is_source(particle.species)
+            {
+                continue;
+            }
+
+            let density = if particle.is_gas {
+                particle.gas_density
+            } else {
+                let distance = self.quadtree.get_neighbour_distance(
+                    &self.particles,
+                    particle.pos,
+                    n_neighbours,
+                    &mut candidates,
+                );
+                if distance > 0.0 {
+                    particle.mass * n_neighbours as f32
+                        / (std::f32::consts::PI * distance * distance)
+                } else {
+                    0.0
+                }
+            };
+            if density > threshold {
+                dense.push((particle_i, density));
+            }
+        }
+        dense.sort_unstable_by(|a, b| b.1.total_cmp(&a.1));
+
+        for (particle_i, _) in dense {
+            let is_taken = self.particles.iter().any(|sink| {
+                return sink.is_sink
+                    && (sink.pos - self.particles[particle_i].pos).length()
+                        < sink.accretion_radius;
+            });
+            if is_taken {
+                continue;
+            }
+
+            let particle = &mut self.particles[particle_i];
+            particle.is_sink = true;
+            particle.is_gas = false;
+            particle.accretion_radius = self.sink_formation.accretion_radius;
+            self.accretion_events.push(AccretionEvent {
+                time: self.time,
+                sink_id: particle.id,
+                particle_id: particle.id,
+                accreted_mass: particle.mass,
+                sink_mass: particle.mass,
+            });
+        }
+    }
+
+    // every particle bound to a sink inside its accretion radius goes to the one it is most
+    // bound to. the sink keeps the total mass and momentum and sits at the center of mass, the
+    // orbital angular momentum turns into spin like for merges. sinks never swallow each other
+    pub fn accrete_onto_sinks(&mut self) {
+        let mut claims = Vec::new();
+        let mut candidates = Vec::new();
+
+        for sink_i in 0..self.particles.len() {
+            let sink = &self.particles[sink_i];
+            if !sink.is_sink || sink.is_consumed {
+                continue;
+            }
+
+            let corner = Vec2::new(sink.accretion_radius, sink.accretion_radius);
+            candidates.clear();
+            self.quadtree.idx_bound(
+                &Bound::new(sink.pos - corner, sink.pos + corner),
+                &mut candidates,
+            );
+
+            for &particle_i in &candidates {
+                let particle = &self.particles[particle_i];
+                if particle.is_sink || particle.is_consumed || particle.is_tracer {
+                    continue;
+                }
+
+                let energy = accretion_energy(sink, particle);
+                if energy < 0.0 {
+                    claims.push((particle_i, sink_i, energy));
+                }
+            }
+        }
+
+        if claims.is_empty() {
+            return;
+        }
+
+        claims.sort_unstable_by(|a, b| a.0.cmp(&b.0).then(a.2.total_cmp(&b.2)));
+        claims.dedup_by_key(|claim| claim.0);
+
+        for (particle_i, sink_i, _) in claims {
+            self.absorb_into_sink(sink_i, particle_i);
+        }
+
+        self.remove_consumed_particles();
+    }
+
+    // only marks the particle consumed, removing it is up to the caller
+    pub fn absorb_into_sink(&mut self, sink_i: usize, particle_i: usize) {
+        let sink = &self.particles[sink_i];
+        let particle = &self.particles[particle_i];
+
+        let mass = sink.mass + particle.mass;
+        let center = (sink.pos * sink.mass + particle.pos * particle.mass) / mass;
+        let vel = (sink.get_vel() * sink.mass + particle.get_vel() * particle.mass) / mass;
+
+        let mut spin_momentum =
+            sink.inertia * sink.angular_vel + particle.inertia * particle.angular_vel;
+        if self.is_merge_conserving_spin {
+            spin_momentum += (sink.pos - center).cross(sink.get_vel() - vel) * sink.mass;
+            spin_momentum +=
+                (particle.pos - center).cross(particle.get_vel() - vel) * particle.mass;
+        }
+
+        let accreted_mass = particle.mass;
+        let particle_id = particle.id;
+        self.particles[particle_i].is_consumed = true;
+
+        let sink = &mut self.particles[sink_i];
+        sink.mass = mass;
+        let shift = center - sink.pos;
+        sink.shift_pos(shift);
+        sink.set_vel(vel);
+        sink.update_inertia();
+        sink.angular_vel = spin_momentum / sink.inertia;
+
+        self.accretion_events.push(AccretionEvent {
+            time: self.time,
+            sink_id: sink.id,
+            particle_id,
+            accreted_mass,
+            sink_mass: mass,
+        });
     }
 
     pub fn particle_collisions_quadratic(&mut self, dt: f32) {
@@ -215,39 +995,348 @@ impl Container {
                 self.resolve_collision(pt1_i, pt2_i);
             }
         }
+
+        self.apply_collision_events();
     }
 
     pub fn container_collisions(&mut self, dt: f32) {
         for particle in &mut self.particles {
-            let particle_vel = particle.get_vel();
+            self.boundary
+                .resolve_particle(particle, &mut self.diagnostics);
+        }
+    }
+
+    // tracers stay out of every energy, they do not pull back on anything
+    pub fn kinetic_energy(&self) -> f32 {
+        let mut energy = 0.0;
+        for particle in self.particles.iter().filter(|particle| !particle.is_tracer) {
+            energy += 0.5 * particle.mass * particle.get_vel().length_squared();
+            energy += 0.5 * particle.inertia * particle.angular_vel * particle.angular_vel;
+        }
+
+        return energy;
+    }
+
+    // uses the quadtree from the last force calculation, so every species counts as pulling on
+    // every other one even when the gravity matrix says otherwise
+    pub fn potential_energy(&self) -> f32 {
+        let mut energy = 0.0;
+        for (particle_i, particle) in self.particles.iter().enumerate() {
+            if particle.is_tracer {
+                continue;
+            }
+            energy += 0.5
+                * particle.mass
+                * self
+                    .quadtree
+                    .get_grav_potential(particle_i, particle.pos, particle.softening);
+        }
+
+        return energy * GRAVITY_CONST + self.external_energy() + self.centrifugal_energy();
+    }
+
+    // -1/2 omega^2 r^2, with it the total is the jacobi integral which is what a rotating
+    // frame conserves
+    pub fn centrifugal_energy(&self) -> f32 {
+        let mut energy = 0.0;
+        for particle in self.particles.iter().filter(|particle| !particle.is_tracer) {
+            energy -= 0.5 * particle.mass * particle.pos.length_squared();
+        }
+
+        return energy * self.pattern_speed * self.pattern_speed;
+    }
+
+    // the external potentials are fixed so there is no half for double counting
+    pub fn external_energy(&self) -> f32 {
+        let mut energy = 0.0;
+        for potential in &self.external_potentials {
+            if !potential.is_in_energy {
+                continue;
+            }
+            for particle in self.particles.iter().filter(|particle| !particle.is_tracer) {
+                energy += particle.mass * potential.potential(particle.pos);
+            }
+        }
+
+        return energy;
+    }
+
+    // centrifugal always. coriolis depends on the velocity so the kicks take care of it, except
+    // for hermite which needs it in acc and jerk. goes after every other force for that jerk
+    pub fn apply_frame_forces(&mut self, is_coriolis_in_acc: bool) {
+        let omega = self.pattern_speed;
+        if omega == 0.0 {
+            return;
+        }
+
+        // -2 omega x u for omega along z
+        let coriolis = |u: Vec2| u.perp() * (-2.0 * omega);
+
+        for particle in &mut self.particles {
+            if !particle.is_active {
+                continue;
+            }
 
-            if particle.pos.x - particle.radius < -1.0 {
-                particle.pos = Vec2::new(-1.0 + particle.radius, particle.pos.y);
-                particle.set_vel(Vec2::new((particle_vel.x).abs(), particle_vel.y));
-            } else if particle.pos.x + particle.radius > 1.0 {
-                particle.pos = Vec2::new(1.0 - particle.radius, particle.pos.y);
-                particle.set_vel(Vec2::new(-(particle_vel.x).abs(), particle_vel.y));
+            particle.acc += particle.pos * (omega * omega);
+            if is_coriolis_in_acc {
+                particle.acc += coriolis(particle.vel);
+                particle.jerk += particle.vel * (omega * omega) + coriolis(particle.acc);
             }
+        }
+    }
 
-            if particle.pos.y - particle.radius < -1.0 {
-                particle.pos = Vec2::new(particle.pos.x, -1.0 + particle.radius);
-                particle.set_vel(Vec2::new(particle_vel.x, (particle_vel.y).abs()));
-            } else if particle.pos.y + particle.radius > 1.0 {
-                particle.pos = Vec2::new(particle.pos.x, 1.0 - particle.radius);
-                particle.set_vel(Vec2::new(particle_vel.x, -(particle_vel.y).abs()));
+    // pressure and viscosity between the gas particles, nothing happens without any gas
+    pub fn hydro_forces(&mut self) {
+        self.sph.compute(&mut self.particles);
+    }
+
+    pub fn thermal_energy(&self) -> f32 {
+        return self.sph.thermal_energy(&self.particles);
+    }
+
+    pub fn advance_scale_factor(&mut self, dt: f32) {
+        self.prev_scale_factor = self.scale_factor;
+        self.scale_factor = self.cosmology.advance(self.scale_factor, dt);
+    }
+
+    // peculiar gravity in comoving coordinates, only the freshly computed accelerations
+    pub fn scale_comoving_gravity(&mut self) {
+        let factor = 1.0 / (self.scale_factor * self.scale_factor * self.scale_factor);
+        for particle in &mut self.particles {
+            if particle.is_active {
+                particle.acc = particle.acc * factor;
+                particle.jerk = particle.jerk * factor;
             }
         }
     }
 
-    pub fn apply_gravity(&mut self) {
+    // x'' = -2 H x' on its own is x' ~ a^-2, done over the whole sub step at once. wraps again
+    // after the hermite correction and the collisions
+    pub fn hubble_drag(&mut self) {
+        let ratio = self.prev_scale_factor / self.scale_factor;
         for particle in &mut self.particles {
-            particle.apply_force(Vec2::new(0.0, GLOBAL_GRAVITY_CONST) * particle.mass);
+            particle.vel = particle.vel * (ratio * ratio);
+        }
+        self.wrap_periodic();
+    }
+
+    // direct summation between the compact particles, the solver already did everyone else.
+    // their pair force there was softened, that is swapped for the point mass one first, which
+    // is exact after the direct and tree solvers but not after the mesh. the terms depend on the
+    // velocities, so those are brought up to the positions with the newtonian acc first or the
+    // 1pn part slowly pumps energy into the orbit. hermite gets no jerk for the corrections
+    pub fn post_newtonian_forces(&mut self) {
+        let compact: Vec<usize> = (0..self.particles.len())
+            .filter(|&particle_i| {
+                let particle = &self.particles[particle_i];
+                return particle.is_compact && !particle.is_consumed && !particle.is_tracer;
+            })
+            .collect();
+
+        // newtonian part first, the corrections below need the whole of it for the velocities
+        let mut point_accs = vec![Vec2::zero(); compact.len()];
+        for (i, &pt1_i) in compact.iter().enumerate() {
+            for (j, &pt2_i) in compact.iter().enumerate().skip(i + 1) {
+                let pt1 = &self.particles[pt1_i];
+                let pt2 = &self.particles[pt2_i];
+                let delta = pt2.pos - pt1.pos;
+                let delta_length_squared = delta.length_squared();
+                if delta_length_squared == 0.0 {
+                    continue;
+                }
+
+                let length = pt1.softening.max(pt2.softening);
+                let point_factor = 1.0 / (delta_length_squared * delta_length_squared.sqrt())
+                    - self.softening.force_factor(delta_length_squared, length);
+                let point_acc = delta * (point_factor * GRAVITY_CONST);
+
+                if self.gravity_matrix.get(pt1.species, pt2.species) {
+                    point_accs[i] += point_acc * pt2.mass;
+                }
+                if self.gravity_matrix.get(pt2.species, pt1.species) {
+                    point_accs[j] += -point_acc * pt1.mass;
+                }
+            }
+        }
+
+        let mut synced = Vec::new();
+        for (i, &particle_i) in compact.iter().enumerate() {
+            let particle = &mut self.particles[particle_i];
+            if particle.is_active {
+                particle.acc += point_accs[i];
+            }
+
+            let mut copy = Particle::new();
+            copy.pos = particle.pos;
+            copy.mass = particle.mass;
+            copy.vel =
+                particle.vel + particle.acc * (self.velocity_lag / (1 << particle.time_bin) as f32);
+            synced.push(copy);
+        }
+
+        for (i, &pt1_i) in compact.iter().enumerate() {
+            if !self.particles[pt1_i].is_active {
+                continue;
+            }
+
+            for (j, &pt2_i) in compact.iter().enumerate() {
+                let species1 = self.particles[pt1_i].species;
+                let species2 = self.particles[pt2_i].species;
+                if i == j || !self.gravity_matrix.get(species1, species2) {
+                    continue;
+                }
+
+                self.particles[pt1_i].acc +=
+                    self.post_newtonian.acceleration(&synced[i], &synced[j]);
+            }
+        }
+    }
+
+    pub fn get_redshift(&self) -> f32 {
+        return 1.0 / self.scale_factor - 1.0;
+    }
+
+    pub fn is_drag_enabled(&self) -> bool {
+        return self.species.iter().any(|species| species.drag.is_enabled());
+    }
+
+    // split off from the integrator and done exactly, so any stopping time is stable. the flow
+    // is given in the inertial frame
+    pub fn apply_drag(&mut self, dt: f32) {
+        for particle in &mut self.particles {
+            let drag = &self.species[particle.species as usize].drag;
+            if !drag.is_enabled() {
+                continue;
+            }
+
+            let gas_vel = self.background_flow.get_velocity(particle.pos)
+                - particle.pos.perp() * self.pattern_speed;
+            particle.vel = drag.apply(particle.vel, gas_vel, particle.radius, dt);
+        }
+    }
+
+    // on top of whatever gravity already went into acc, jerk too for the hermite integrator
+    pub fn apply_external_potentials(&mut self) {
+        for potential in &self.external_potentials {
+            for particle in &mut self.particles {
+                if !particle.is_active {
+                    continue;
+                }
+
+                particle.acc += potential.acceleration(particle.pos);
+                particle.jerk += potential.jerk(particle.pos, particle.vel);
+            }
         }
     }
 
     pub fn integrate_particles(&mut self, dt: f32) {
         for particle in &mut self.particles {
-            particle.integrate(dt);
+            particle.integrate(dt, self.pattern_speed);
+        }
+        self.wrap_periodic();
+    }
+
+    pub fn kick_particles(&mut self, dt: f32) {
+        for particle in &mut self.particles {
+            particle.kick_rotating(dt, self.pattern_speed);
+        }
+    }
+
+    pub fn drift_particles(&mut self, dt: f32) {
+        for particle in &mut self.particles {
+            particle.drift(dt);
+        }
+        self.wrap_periodic();
+    }
+
+    pub fn hermite_predict_particles(&mut self, dt: f32) {
+        for particle in &mut self.particles {
+            particle.hermite_predict(dt);
+        }
+        self.wrap_periodic();
+    }
+
+    // comoving runs are periodic, every move goes straight back into the box so the tree and
+    // the mesh never see anything outside of it
+    pub fn wrap_periodic(&mut self) {
+        if !self.cosmology.is_enabled() {
+            return;
+        }
+        for particle in &mut self.particles {
+            particle.pos = wrap(particle.pos);
+        }
+    }
+
+    pub fn hermite_correct_particles(&mut self, dt: f32) {
+        for particle in &mut self.particles {
+            particle.hermite_correct(dt);
+        }
+    }
+
+    pub fn start_step(&mut self) {
+        for particle in &mut self.particles {
+            particle.start_step();
+        }
+    }
+
+    pub fn reset_acc(&mut self) {
+        for particle in &mut self.particles {
+            if particle.is_active {
+                particle.reset_acc();
+            }
+        }
+    }
+
+    pub fn set_all_active(&mut self) {
+        for particle in &mut self.particles {
+            particle.is_active = true;
+        }
+    }
+
+    // block timesteps: kicks the particles whose step starts (or ends) on this micro step
+    pub fn kick_bins(&mut self, micro_step_i: u32, n_levels: u32, max_dt: f32) {
+        for particle in &mut self.particles {
+            let n_micro_steps = bin_step_count(particle.time_bin, n_levels);
+            if micro_step_i.is_multiple_of(n_micro_steps) {
+                particle.kick_rotating(
+                    max_dt / (1 << particle.time_bin) as f32 * 0.5,
+                    self.pattern_speed,
+                );
+            }
+        }
+    }
+
+    pub fn mark_active_bins(&mut self, micro_step_i: u32, n_levels: u32) {
+        for particle in &mut self.particles {
+            particle.is_active =
+                micro_step_i.is_multiple_of(bin_step_count(particle.time_bin, n_levels));
+        }
+    }
+
+    // only the active particles can move bins. going to a shorter step is always fine,
+    // a longer one has to line up with the current micro step
+    pub fn assign_time_bins(
+        &mut self,
+        micro_step_i: u32,
+        n_levels: u32,
+        max_dt: f32,
+        accuracy: f32,
+    ) {
+        for particle in &mut self.particles {
+            if !particle.is_active {
+                continue;
+            }
+
+            let timestep_length = timestep_length(particle, &self.softening);
+            let dt = acceleration_timestep(particle.acc, timestep_length, accuracy)
+                .min(sph_timestep(particle, SPH_COURANT));
+            let new_bin = timestep_bin(max_dt, dt, n_levels);
+
+            if new_bin >= particle.time_bin
+                || micro_step_i.is_multiple_of(bin_step_count(new_bin, n_levels))
+            {
+                particle.time_bin = new_bin;
+            }
         }
     }
 
@@ -0,0 +1,320 @@
+use crate::complex::Complex;
+use crate::fft::fft_2d;
+use crate::functions::erfc;
+use crate::particle::Particle;
+use crate::vector::Vec2;
+
+use std::f64::consts::PI;
+
+// average of 1/r over a square cell seen from its center, times the cell size
+const CELL_AVERAGE_INVERSE_DISTANCE: f64 = 3.525494348078172; // 4 ln(1 + sqrt(2))
+
+// particle mesh gravity on the same [-1, 1] square as the quadtree, same 1/r potential.
+// masses go onto the grid with cloud in cell, the potential comes from an fft convolution with
+// the green's function and the forces are finite differences read back with cloud in cell.
+// isolated grids are zero padded to twice the size so the images never see each other,
+// periodic grids wrap and drop the mean density
+pub struct ParticleMesh {
+    n_cells: usize,
+    is_periodic: bool,
+    // treepm split in cells, 0 uses the whole 1/r kernel on the mesh. otherwise the mesh only
+    // carries erf(r / 2 r_s) / r and the tree does the rest
+    split_scale: f32,
+    // periodic only. every particle is a column through a cube as deep as the grid is wide,
+    // so the perturbations follow the 3d poisson equation, 4 pi / k^2, instead of 2 pi / k.
+    // what a friedmann background needs, the split scale is ignored
+    is_columns: bool,
+
+    // fft of the green's function on the (padded) grid, rebuilt when the settings change
+    green: Vec<Complex>,
+    is_green_ready: bool,
+
+    density: Vec<Complex>,
+    potential: Vec<f32>,
+    force: Vec<Vec2>,
+}
+
+impl ParticleMesh {
+    pub fn new(n_cells: usize, is_periodic: bool, split_scale: f32) -> Self {
+        return ParticleMesh {
+            n_cells,
+            is_periodic,
+            split_scale,
+            is_columns: false,
+            green: Vec::new(),
+            is_green_ready: false,
+            density: Vec::new(),
+            potential: Vec::new(),
+            force: Vec::new(),
+        };
+    }
+
+    // has to be a power of two for the fft
+    pub fn set_grid(&mut self, n_cells: usize) {
+        self.n_cells = n_cells;
+        self.is_green_ready = false;
+    }
+
+    pub fn set_periodic(&mut self, is_periodic: bool) {
+        self.is_periodic = is_periodic;
+        self.is_green_ready = false;
+    }
+
+    pub fn set_columns(&mut self, is_columns: bool) {
+        self.is_columns = is_columns;
+        self.is_green_ready = false;
+    }
+
+    pub fn set_split_scale(&mut self, split_scale: f32) {
+        self.split_scale = split_scale;
+        self.is_green_ready = false;
+    }
+
+    pub fn get_cell_size(&self) -> f32 {
+        return 2.0 / self.n_cells as f32;
+    }
+
+    // r_s in the same units as the positions
+    pub fn get_split_radius(&self) -> f32 {
+        return self.split_scale * self.get_cell_size();
+    }
+
+    fn fft_size(&self) -> usize {
+        if self.is_periodic {
+            return self.n_cells;
+        }
+        return 2 * self.n_cells;
+    }
+
+    fn build_green(&mut self) {
+        let size = self.fft_size();
+        let h = self.get_cell_size() as f64;
+        let rs = self.get_split_radius() as f64;
+
+        // real space kernel on the nearest image distances. for a periodic grid only the short
+        // range part is sampled here and taken off the exact 2 pi / k below
+        self.green = vec![Complex::zero(); size * size];
+        for y in 0..size {
+            for x in 0..size {
+                let dx = x.min(size - x) as f64 * h;
+                let dy = y.min(size - y) as f64 * h;
+                let r = (dx * dx + dy * dy).sqrt();
+
+                let value = if self.is_periodic {
+                    if rs == 0.0 {
+                        0.0
+                    } else if r == 0.0 {
+                        CELL_AVERAGE_INVERSE_DISTANCE / h - 1.0 / (rs * PI.sqrt())
+                    } else {
+                        erfc(r / (2.0 * rs)) / r
+                    }
+                } else if rs == 0.0 {
+                    if r == 0.0 {
+                        CELL_AVERAGE_INVERSE_DISTANCE / h
+                    } else {
+                        1.0 / r
+                    }
+                } else if r == 0.0 {
+                    1.0 / (rs * PI.sqrt())
+                } else {
+                    (1.0 - erfc(r / (2.0 * rs))) / r
+                };
+
+                self.green[y * size + x] = Complex::new(value, 0.0);
+            }
+        }
+
+        fft_2d(&mut self.green, size, false);
+
+        if self.is_periodic {
+            // the 2d transform of 1/r is 2 pi / k, over the cell area to match the sampled sums
+            let k_unit = 2.0 * PI / 2.0;
+            for y in 0..size {
+                for x in 0..size {
+                    let kx = (if x <= size / 2 {
+                        x as f64
+                    } else {
+                        x as f64 - size as f64
+                    }) * k_unit;
+                    let ky = (if y <= size / 2 {
+                        y as f64
+                    } else {
+                        y as f64 - size as f64
+                    }) * k_unit;
+                    let k = (kx * kx + ky * ky).sqrt();
+
+                    let idx = y * size + x;
+                    if k == 0.0 {
+                        self.green[idx] = Complex::zero();
+                    } else if self.is_columns {
+                        // the column mass is spread over the depth of 2
+                        self.green[idx] = Complex::new(4.0 * PI / (k * k * h * h * 2.0), 0.0);
+                    } else {
+                        self.green[idx] =
+                            Complex::new(2.0 * PI / (k * h * h), 0.0) - self.green[idx];
+                    }
+                }
+            }
+        }
+
+        self.is_green_ready = true;
+    }
+
+    // the two cells on each axis around pos and the weight of the first one. None when pos is
+    // off an isolated grid
+    fn cic_cells(&self, pos: Vec2) -> Option<([usize; 2], [usize; 2], Vec2)> {
+        let n = self.n_cells as i64;
+        let h = self.get_cell_size();
+
+        let fx = (pos.x + 1.0) / h - 0.5;
+        let fy = (pos.y + 1.0) / h - 0.5;
+        let x0 = fx.floor() as i64;
+        let y0 = fy.floor() as i64;
+        let weight = Vec2::new(1.0 - (fx - x0 as f32), 1.0 - (fy - y0 as f32));
+
+        if self.is_periodic {
+            let xs = [x0.rem_euclid(n) as usize, (x0 + 1).rem_euclid(n) as usize];
+            let ys = [y0.rem_euclid(n) as usize, (y0 + 1).rem_euclid(n) as usize];
+            return Some((xs, ys, weight));
+        }
+
+        if x0 < -1 || y0 < -1 || x0 >= n || y0 >= n {
+            return None;
+        }
+
+        // cells hanging off the edge get clamped, their weight still lands on the grid
+        let xs = [x0.max(0) as usize, (x0 + 1).min(n - 1) as usize];
+        let ys = [y0.max(0) as usize, (y0 + 1).min(n - 1) as usize];
+        return Some((xs, ys, weight));
+    }
+
+    fn neighbour(&self, i: usize, offset: i64) -> usize {
+        let n = self.n_cells as i64;
+        let j = i as i64 + offset;
+
+        if self.is_periodic {
+            return j.rem_euclid(n) as usize;
+        }
+        return j.clamp(0, n - 1) as usize;
+    }
+
+    // only particles of the species in source_mask leave mass on the grid
+    pub fn compute(&mut self, particles: &Vec<Particle>, source_mask: u32) {
+        if !self.is_green_ready {
+            self.build_green();
+        }
+
+        let n = self.n_cells;
+        let size = self.fft_size();
+
+        self.density.clear();
+        self.density.resize(size * size, Complex::zero());
+        for particle in particles {
+            if source_mask & (1 << particle.species) == 0 {
+                continue;
+            }
+            if let Some((xs, ys, weight)) = self.cic_cells(particle.pos) {
+                let wx = [weight.x, 1.0 - weight.x];
+                let wy = [weight.y, 1.0 - weight.y];
+
+                for a in 0..2 {
+                    for b in 0..2 {
+                        let mass = (particle.get_source_mass() * wx[a] * wy[b]) as f64;
+                        self.density[ys[b] * size + xs[a]].re += mass;
+                    }
+                }
+            }
+        }
+
+        fft_2d(&mut self.density, size, false);
+        for idx in 0..size * size {
+            self.density[idx] = self.density[idx] * self.green[idx];
+        }
+        fft_2d(&mut self.density, size, true);
+
+        self.potential.clear();
+        for y in 0..n {
+            for x in 0..n {
+                self.potential.push(self.density[y * size + x].re as f32);
+            }
+        }
+
+        // 4 point central differences, the force is the gradient of sum m / r
+        let h = self.get_cell_size();
+        self.force.clear();
+        for y in 0..n {
+            for x in 0..n {
+                let p = |xi: usize, yi: usize| self.potential[yi * n + xi];
+
+                let fx = 8.0 * (p(self.neighbour(x, 1), y) - p(self.neighbour(x, -1), y))
+                    - (p(self.neighbour(x, 2), y) - p(self.neighbour(x, -2), y));
+                let fy = 8.0 * (p(x, self.neighbour(y, 1)) - p(x, self.neighbour(y, -1)))
+                    - (p(x, self.neighbour(y, 2)) - p(x, self.neighbour(y, -2)));
+
+                self.force.push(Vec2::new(fx, fy) / (12.0 * h));
+            }
+        }
+    }
+
+    // same units as QuadTree::get_grav_force, only valid after compute
+    pub fn get_grav_force(&self, pos: Vec2) -> Vec2 {
+        let Some((xs, ys, weight)) = self.cic_cells(pos) else {
+            return Vec2::zero();
+        };
+        let wx = [weight.x, 1.0 - weight.x];
+        let wy = [weight.y, 1.0 - weight.y];
+
+        let mut force = Vec2::zero();
+        for a in 0..2 {
+            for b in 0..2 {
+                force += self.force[ys[b] * self.n_cells + xs[a]] * (wx[a] * wy[b]);
+            }
+        }
+
+        return force;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::particle::Particle;
+    use crate::particle_container::Container;
+    use crate::vector::Vec2;
+
+    // a sunflower spiral, spread out without needing an rng
+    fn spiral_container(n_particles: usize, radius: f32) -> Container {
+        let mut container = Container::new();
+        for particle_i in 0..n_particles {
+            let angle = particle_i as f32 * 2.3999632;
+            let distance = radius * ((particle_i as f32 + 0.5) / n_particles as f32).sqrt();
+            let mut particle = Particle::new();
+            particle.set_pos(Vec2::new(angle.cos(), angle.sin()) * distance);
+            particle.mass = 1.0 + (particle_i % 3) as f32;
+            container.push_particle(particle);
+        }
+        return container;
+    }
+
+    // the mesh smooths everything below a couple of cells, so only the average is close
+    #[test]
+    fn matches_direct_sum() {
+        let mut container = spiral_container(200, 0.6);
+        container.reset_acc();
+        container.interparticle_gravity_quadratic();
+        let direct: Vec<Vec2> = container
+            .particles
+            .iter()
+            .map(|particle| particle.acc)
+            .collect();
+
+        container.reset_acc();
+        container.interparticle_gravity_pm();
+
+        let mut mean_error = 0.0;
+        for (particle, acc) in container.particles.iter().zip(&direct) {
+            mean_error += (particle.acc - *acc).length() / acc.length();
+        }
+        mean_error /= direct.len() as f32;
+        assert!(mean_error < 0.01, "pm mean relative error {}", mean_error);
+    }
+}
@@ -0,0 +1,89 @@
+use crate::constants::GRAVITY_CONST;
+use crate::particle::Particle;
+use crate::vector::Vec2;
+
+// post newtonian terms between compact particles, harmonic coordinates as in blanchet's living
+// review. 1pn makes orbits precess, 2.5pn is the radiation reaction that shrinks them. only the
+// corrections come out of here, the newtonian part stays with the gravity solvers
+#[derive(Clone, Copy)]
+pub struct PostNewtonian {
+    // in simulation units, 0 turns it off
+    pub speed_of_light: f32,
+    pub is_using_1pn: bool,
+    pub is_using_25pn: bool,
+}
+
+impl PostNewtonian {
+    pub fn new(speed_of_light: f32) -> PostNewtonian {
+        return PostNewtonian {
+            speed_of_light,
+            is_using_1pn: true,
+            is_using_25pn: true,
+        };
+    }
+
+    pub fn disabled() -> PostNewtonian {
+        return PostNewtonian::new(0.0);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        return self.speed_of_light > 0.0;
+    }
+
+    // what pt2 adds to the acceleration of pt1 on top of newton. double precision, c^5 gets
+    // large quickly
+    pub fn acceleration(&self, pt1: &Particle, pt2: &Particle) -> Vec2 {
+        let offset = pt1.pos - pt2.pos;
+        let r = offset.length() as f64;
+        if r == 0.0 {
+            return Vec2::zero();
+        }
+
+        let c = self.speed_of_light as f64;
+        let c2 = c * c;
+        let gm1 = GRAVITY_CONST as f64 * pt1.mass as f64;
+        let gm2 = GRAVITY_CONST as f64 * pt2.mass as f64;
+
+        let n = [offset.x as f64 / r, offset.y as f64 / r];
+        let v1 = [pt1.vel.x as f64, pt1.vel.y as f64];
+        let v2 = [pt2.vel.x as f64, pt2.vel.y as f64];
+        let v12 = [v1[0] - v2[0], v1[1] - v2[1]];
+        let dot = |a: [f64; 2], b: [f64; 2]| a[0] * b[0] + a[1] * b[1];
+
+        // acc = n_factor * n + v_factor * v12
+        let mut n_factor = 0.0;
+        let mut v_factor = 0.0;
+
+        if self.is_using_1pn {
+            let n_v2 = dot(n, v2);
+            n_factor += (5.0 * gm1 * gm2 / (r * r * r)
+                + 4.0 * gm2 * gm2 / (r * r * r)
+                + gm2 / (r * r)
+                    * (1.5 * n_v2 * n_v2 - dot(v1, v1) + 4.0 * dot(v1, v2) - 2.0 * dot(v2, v2)))
+                / c2;
+            v_factor += gm2 / (r * r) * (4.0 * dot(n, v1) - 3.0 * n_v2) / c2;
+        }
+
+        if self.is_using_25pn {
+            let c5 = c2 * c2 * c;
+            let r3 = r * r * r;
+            let r4 = r3 * r;
+            let n_v12 = dot(n, v12);
+            let v12_squared = dot(v12, v12);
+
+            n_factor += (208.0 / 15.0 * gm1 * gm2 * gm2 / r4 * n_v12
+                - 24.0 / 5.0 * gm1 * gm1 * gm2 / r4 * n_v12
+                + 12.0 / 5.0 * gm1 * gm2 / r3 * v12_squared * n_v12)
+                / c5;
+            v_factor += (8.0 / 5.0 * gm1 * gm1 * gm2 / r4
+                - 32.0 / 5.0 * gm1 * gm2 * gm2 / r4
+                - 4.0 / 5.0 * gm1 * gm2 / r3 * v12_squared)
+                / c5;
+        }
+
+        return Vec2::new(
+            (n_factor * n[0] + v_factor * v12[0]) as f32,
+            (n_factor * n[1] + v_factor * v12[1]) as f32,
+        );
+    }
+}
@@ -1,12 +1,15 @@
-use crate::constants::{EPS_SQUARED, THETA_SQUARED};
+use crate::constants::{EPS, SHORT_RANGE_CUTOFF, THETA_SQUARED};
+use crate::functions::erfc;
 use crate::particle::{self, Particle};
+use crate::softening::{PLUMMER_SOFTENING, Softening};
 use crate::vector::Vec2;
 
+#[derive(Clone)]
 pub struct Bound {
     top_right: Vec2,
     bot_left: Vec2,
-    center: Vec2,
-    size_squared: f32,
+    pub center: Vec2,
+    pub size_squared: f32,
 }
 
 impl Bound {
@@ -36,6 +39,23 @@ impl Bound {
         return true;
     }
 
+    // overlap with self grown by margin on every side
+    pub fn is_overlap_margin(&self, other: &Bound, margin: f32) -> bool {
+        if self.top_right.x + margin <= other.bot_left.x
+            || self.bot_left.x - margin >= other.top_right.x
+        {
+            return false;
+        }
+
+        if self.top_right.y + margin <= other.bot_left.y
+            || self.bot_left.y - margin >= other.top_right.y
+        {
+            return false;
+        }
+
+        return true;
+    }
+
     pub fn get_sector(&self, pos: Vec2) -> usize {
         return ((pos.y > self.center.y) as usize) << 1 | (pos.x > self.center.x) as usize;
     }
@@ -48,16 +68,23 @@ impl Bound {
     }
 }
 
+#[derive(Clone)]
 pub struct QNode {
-    bound: Bound,
+    pub bound: Bound,
     depth: u32,
-    children: usize,
-    next: usize,
-    is_leaf: bool,
-    particle_contained: i32,
-
-    total_mass: f32,
-    center_mass: Vec2,
+    pub children: usize,
+    pub next: usize,
+    pub is_leaf: bool,
+    pub particle_contained: i32,
+
+    pub total_mass: f32,
+    pub center_mass: Vec2,
+    // largest softening length of anything inside
+    pub max_softening: f32,
+    // largest particle radius inside, the disks can stick out of the node by this much
+    pub max_radius: f32,
+    // traceless quadrupole about center_mass, sum of m * (3 x x^T - r^2 I) stored as xx, xy, yy
+    quadrupole: [f32; 3],
 }
 
 impl QNode {
@@ -71,34 +98,87 @@ impl QNode {
             particle_contained: -1,
             total_mass: 0.0,
             center_mass: Vec2::zero(),
+            max_softening: 0.0,
+            max_radius: 0.0,
+            quadrupole: [0.0; 3],
         };
     }
 
     pub fn set_particle(&mut self, particles: &Vec<Particle>, pt_idx: i32) {
         self.particle_contained = pt_idx;
 
-        self.total_mass += particles[pt_idx as usize].mass;
+        self.total_mass += particles[pt_idx as usize].get_source_mass();
         self.center_mass = particles[pt_idx as usize].pos;
+        self.max_softening = self.max_softening.max(particles[pt_idx as usize].softening);
+        self.max_radius = self.max_radius.max(particles[pt_idx as usize].radius);
+    }
+
+    // q r for r measured from the center of mass
+    fn quadrupole_dot(&self, r: Vec2) -> Vec2 {
+        let q = &self.quadrupole;
+        return Vec2::new(q[0] * r.x + q[1] * r.y, q[1] * r.x + q[2] * r.y);
+    }
+
+    // quadrupole part of the acceleration (without G) at offset r from the center of mass,
+    // q r / r^5 - 5/2 (r^T q r) r / r^7
+    fn quadrupole_force(&self, r: Vec2, distance_squared: f32) -> Vec2 {
+        let q_r = self.quadrupole_dot(r);
+        let inv_r2 = 1.0 / distance_squared;
+        let inv_r5 = inv_r2 * inv_r2 / distance_squared.sqrt();
+
+        return (q_r - r * (2.5 * r.dot(q_r) * inv_r2)) * inv_r5;
+    }
+
+    // quadrupole part of the potential (without G), -1/2 (r^T q r) / r^5
+    fn quadrupole_potential(&self, r: Vec2, distance_squared: f32) -> f32 {
+        let inv_r5 = 1.0 / (distance_squared * distance_squared * distance_squared.sqrt());
+        return -0.5 * r.dot(self.quadrupole_dot(r)) * inv_r5;
     }
 }
 
+#[derive(Clone)]
 pub struct QuadTree {
-    stack: Vec<QNode>,
+    pub stack: Vec<QNode>,
     dfs_stack: Vec<usize>,
+
+    // opening angle, a node is used as a whole when size / distance < theta
+    theta_squared: f32,
+    is_using_quadrupole: bool,
+    softening: Softening,
 }
 
 impl QuadTree {
     pub fn new() -> Self {
-        return QuadTree {
-            stack: vec![QNode::new(Vec2::new(-1.0, -1.0), Vec2::new(2.0, 2.0), 0, 0)],
+        let mut quadtree = QuadTree {
+            stack: Vec::new(),
             dfs_stack: vec![0],
+            theta_squared: THETA_SQUARED,
+            is_using_quadrupole: true,
+            softening: Softening::new(PLUMMER_SOFTENING, EPS),
         };
+        quadtree.reset();
+
+        return quadtree;
+    }
+
+    pub fn set_theta(&mut self, theta: f32) {
+        self.theta_squared = theta * theta;
+    }
+
+    pub fn set_quadrupole(&mut self, is_using_quadrupole: bool) {
+        self.is_using_quadrupole = is_using_quadrupole;
+    }
+
+    pub fn set_softening(&mut self, softening: Softening) {
+        self.softening = softening;
     }
 
     pub fn reset(&mut self) {
         self.stack.clear();
         self.stack
             .push(QNode::new(Vec2::new(-1.0, -1.0), Vec2::new(2.0, 2.0), 0, 0));
+        // every walk starts at the first child, so the root is split even for a single particle
+        self.subdivide_node(0);
     }
 
     pub fn subdivide_node(&mut self, node_i: usize) {
@@ -106,6 +186,9 @@ impl QuadTree {
         self.stack[node_i].particle_contained = -1;
         self.stack[node_i].total_mass = 0.0;
         self.stack[node_i].center_mass = Vec2::zero();
+        self.stack[node_i].quadrupole = [0.0; 3];
+        self.stack[node_i].max_softening = 0.0;
+        self.stack[node_i].max_radius = 0.0;
 
         let new_bound_dim = self.stack[node_i].bound.get_dim() * 0.5;
 
@@ -221,6 +304,37 @@ impl QuadTree {
         }
     }
 
+    // every particle whose disk reaches into other_bound, not just the ones centered in it.
+    // big particles get found from the small ones too. needs propogate_mass for the radii
+    pub fn idx_contacts(&self, other_bound: &Bound, add_vec: &mut Vec<usize>) {
+        let mut node_i = 1;
+
+        loop {
+            if node_i == 0 {
+                break;
+            }
+            let curr_node = &self.stack[node_i];
+
+            if !curr_node
+                .bound
+                .is_overlap_margin(other_bound, curr_node.max_radius)
+            {
+                node_i = curr_node.next;
+                continue;
+            }
+
+            if curr_node.is_leaf {
+                if curr_node.particle_contained != -1 {
+                    add_vec.push(curr_node.particle_contained as usize);
+                }
+                node_i = curr_node.next;
+                continue;
+            }
+
+            node_i = curr_node.children
+        }
+    }
+
     // pub fn propogate_collision(){
 
     // }
@@ -235,20 +349,63 @@ impl QuadTree {
 
             let mut center_mass = Vec2::zero();
             let mut total_mass = 0.0;
+            let mut max_softening: f32 = 0.0;
+            let mut max_radius: f32 = 0.0;
 
             for child_i in child_start_i..child_start_i + 4 {
                 center_mass += self.stack[child_i].center_mass * self.stack[child_i].total_mass;
                 total_mass += self.stack[child_i].total_mass;
+                max_softening = max_softening.max(self.stack[child_i].max_softening);
+                max_radius = max_radius.max(self.stack[child_i].max_radius);
             }
 
             // println!("{}", center_mass / total_mass);
 
-            self.stack[nd_i].center_mass = center_mass / total_mass;
+            // only tracers below, they still need a spot for the walks
+            let center_mass = if total_mass > 0.0 {
+                center_mass / total_mass
+            } else {
+                self.stack[nd_i].bound.center
+            };
+
+            // shift every child quadrupole onto the new center (parallel axis)
+            let mut quadrupole = [0.0; 3];
+            for child_i in child_start_i..child_start_i + 4 {
+                let child = &self.stack[child_i];
+                let d = child.center_mass - center_mass;
+                let m = child.total_mass;
+
+                quadrupole[0] += child.quadrupole[0] + m * (2.0 * d.x * d.x - d.y * d.y);
+                quadrupole[1] += child.quadrupole[1] + m * 3.0 * d.x * d.y;
+                quadrupole[2] += child.quadrupole[2] + m * (2.0 * d.y * d.y - d.x * d.x);
+            }
+
+            self.stack[nd_i].center_mass = center_mass;
             self.stack[nd_i].total_mass = total_mass;
+            self.stack[nd_i].quadrupole = quadrupole;
+            self.stack[nd_i].max_softening = max_softening;
+            self.stack[nd_i].max_radius = max_radius;
         }
     }
 
-    pub fn get_grav_force(&mut self, pos: Vec2) -> Vec2 {
+    // moves the leaves onto the current particle positions without rebuilding. the node bounds
+    // go stale so this is only good for gravity between two proper rebuilds
+    pub fn refit(&mut self, particles: &[Particle]) {
+        for node in &mut self.stack {
+            if node.is_leaf && node.particle_contained != -1 {
+                let particle = &particles[node.particle_contained as usize];
+                node.total_mass = particle.get_source_mass();
+                node.center_mass = particle.pos;
+                node.max_softening = particle.softening;
+                node.max_radius = particle.radius;
+            }
+        }
+
+        self.propogate_mass();
+    }
+
+    // softening is the length of the particle at pos, each node uses the larger of it and its own
+    pub fn get_grav_force(&mut self, pos: Vec2, softening: f32) -> Vec2 {
         let mut force = Vec2::zero();
         // let mut dfs_stack = vec![0];
 
@@ -264,17 +421,66 @@ impl QuadTree {
 
             let distance_squared = delta.length_squared();
 
-            if distance_squared < EPS_SQUARED {
-                // println!("{} {} {}", delta, curr_node.center_mass, pos);
+            if curr_node.is_leaf
+                || curr_node.bound.size_squared < distance_squared * self.theta_squared
+            {
+                // the particle itself sits at distance 0 where every kernel gives 0
+                let length = softening.max(curr_node.max_softening);
+                force += delta
+                    * (curr_node.total_mass
+                        * self.softening.force_factor(distance_squared, length));
+                // leaves are single points so they have no quadrupole
+                if self.is_using_quadrupole && !curr_node.is_leaf {
+                    force += curr_node.quadrupole_force(-delta, distance_squared);
+                }
+                node_i = curr_node.next;
+            } else {
+                node_i = curr_node.children;
+            }
+        }
+
+        return force;
+    }
+
+    // short range half of the treepm split, the mesh carries erf(r / 2 r_s) / r. nodes entirely
+    // past the cutoff are skipped and only monopoles are used since the split kernel is not 1/r
+    pub fn get_short_range_force(&self, pos: Vec2, softening: f32, split_radius: f32) -> Vec2 {
+        let mut force = Vec2::zero();
+        let cutoff = SHORT_RANGE_CUTOFF * split_radius;
+
+        let mut node_i = 1;
+
+        loop {
+            if node_i == 0 {
+                break;
+            }
+            let curr_node = &self.stack[node_i];
+
+            // distance from pos to the closest point of the node
+            let half_size = curr_node.bound.size_squared.sqrt() * 0.5;
+            let outside_x = ((pos.x - curr_node.bound.center.x).abs() - half_size).max(0.0);
+            let outside_y = ((pos.y - curr_node.bound.center.y).abs() - half_size).max(0.0);
+            if outside_x * outside_x + outside_y * outside_y > cutoff * cutoff {
                 node_i = curr_node.next;
                 continue;
             }
 
-            if curr_node.is_leaf || curr_node.bound.size_squared < distance_squared * THETA_SQUARED
-            {
-                let denom = (distance_squared + EPS_SQUARED) * distance_squared.sqrt();
+            let delta = curr_node.center_mass - pos;
+            let distance_squared = delta.length_squared();
 
-                force += delta * (curr_node.total_mass / denom);
+            if curr_node.is_leaf
+                || curr_node.bound.size_squared < distance_squared * self.theta_squared
+            {
+                let distance = distance_squared.sqrt();
+                let x = distance / (2.0 * split_radius);
+                let factor = erfc(x as f64) as f32
+                    + distance / (split_radius * std::f32::consts::PI.sqrt()) * (-x * x).exp();
+
+                let length = softening.max(curr_node.max_softening);
+                force += delta
+                    * (curr_node.total_mass
+                        * factor
+                        * self.softening.force_factor(distance_squared, length));
                 node_i = curr_node.next;
             } else {
                 node_i = curr_node.children;
@@ -284,6 +490,94 @@ impl QuadTree {
         return force;
     }
 
+    // particle_i is left out by index, the tree can be older than pos so it may not sit on its
+    // own leaf anymore
+    pub fn get_grav_potential(&self, particle_i: usize, pos: Vec2, softening: f32) -> f32 {
+        let mut potential = 0.0;
+
+        let mut node_i = 1;
+
+        loop {
+            if node_i == 0 {
+                break;
+            }
+            let curr_node = &self.stack[node_i];
+
+            let r = pos - curr_node.center_mass;
+            let distance_squared = r.length_squared();
+
+            // skips the particle itself, plummer would give it a self energy. other particles
+            // right on top of it are skipped like in the force
+            if curr_node.is_leaf
+                && (curr_node.particle_contained == particle_i as i32 || distance_squared == 0.0)
+            {
+                node_i = curr_node.next;
+                continue;
+            }
+
+            if curr_node.is_leaf
+                || curr_node.bound.size_squared < distance_squared * self.theta_squared
+            {
+                let length = softening.max(curr_node.max_softening);
+                potential -= curr_node.total_mass
+                    * self.softening.potential_factor(distance_squared, length);
+                if self.is_using_quadrupole && !curr_node.is_leaf {
+                    potential += curr_node.quadrupole_potential(r, distance_squared);
+                }
+                node_i = curr_node.next;
+            } else {
+                node_i = curr_node.children;
+            }
+        }
+
+        return potential;
+    }
+
+    // distance to the n_neighbours-th closest other particle. grows a search box around pos
+    // until it holds enough particles and the answer fits inside it
+    pub fn get_neighbour_distance(
+        &mut self,
+        particles: &[Particle],
+        pos: Vec2,
+        n_neighbours: usize,
+        candidates: &mut Vec<usize>,
+    ) -> f32 {
+        let mut half_size = self.stack[self.idx_pos(pos)].bound.size_squared.sqrt();
+        let mut distances = Vec::new();
+
+        loop {
+            candidates.clear();
+            let corner = Vec2::new(half_size, half_size);
+            self.idx_bound(&Bound::new(pos - corner, pos + corner), candidates);
+
+            distances.clear();
+            for &particle_i in candidates.iter() {
+                let distance = (particles[particle_i].pos - pos).length();
+                if distance > 0.0 && !particles[particle_i].is_tracer {
+                    distances.push(distance);
+                }
+            }
+
+            // the box already covers the whole tree, there just are not enough particles
+            let is_covering = half_size > 2.9;
+
+            if distances.len() >= n_neighbours {
+                distances.select_nth_unstable_by(n_neighbours - 1, |a, b| a.total_cmp(b));
+                let distance = distances[n_neighbours - 1];
+
+                // only trustworthy if nothing outside the box could be closer
+                if distance <= half_size || is_covering {
+                    return distance;
+                }
+                half_size = distance;
+            } else if is_covering {
+                return distances.iter().fold(0.0, |a: f32, &b| a.max(b));
+            } else {
+                half_size *= 2.0;
+            }
+        }
+    }
+
     pub fn add_particle(&mut self, particle_vec: &Vec<Particle>, particle_idx: usize) {
         let mut curr_node_i = self.idx_pos(particle_vec[particle_idx].pos);
 
@@ -1,25 +1,107 @@
+use crate::boundary::{Boundary, WallMaterial};
+use crate::constants::*;
+use crate::cosmology::Cosmology;
+use crate::diagnostics::DiagnosticsStep;
+use crate::drag::{BackgroundFlow, Drag};
+use crate::events::{AccretionEvent, FragmentEvent, MergeEvent};
+use crate::external::ExternalPotential;
+use crate::fragmentation::Fragmentation;
 use crate::functions::show_progress;
 use crate::particle::*;
 use crate::particle_container::*;
+use crate::pn::PostNewtonian;
 use crate::progress_bar::ProgressBar;
+use crate::sink::SinkFormation;
+use crate::size_distribution::SizeDistribution;
+use crate::softening::{PLUMMER_SOFTENING, Softening};
+use crate::species::{InteractionMatrix, MAX_SPECIES, Species};
+use crate::sph::Sph;
+use crate::timestep::adaptive_timestep;
 use crate::vector::*;
 use crate::functions::*;
 use std::time::Instant;
 
 pub const UNIFORM_DISTRIBUTION: u32 = 0;
 pub const NORMAL_DISTRIBUTION: u32 = 1;
+// zel'dovich displaced grid for the default species, see Cosmology
+pub const ZELDOVICH_DISTRIBUTION: u32 = 2;
+
+pub const VERLET_INTEGRATOR: u32 = 0;
+pub const LEAPFROG_INTEGRATOR: u32 = 1;
+// 4th order, meant for small n runs together with DIRECT_GRAVITY
+pub const YOSHIDA_INTEGRATOR: u32 = 2;
+// 4th order predictor corrector, always uses direct summation since it needs the jerk
+pub const HERMITE_INTEGRATOR: u32 = 3;
+
+pub const TREE_GRAVITY: u32 = 0;
+pub const DIRECT_GRAVITY: u32 = 1;
+// fast multipole on the quadtree, o(n)
+pub const FMM_GRAVITY: u32 = 2;
+// cloud in cell mesh with an fft poisson solve, smooth large scale forces only
+pub const PM_GRAVITY: u32 = 3;
+// mesh for the long range part, tree for the short range part
+pub const TREE_PM_GRAVITY: u32 = 4;
+
+// forest-ruth coefficients, drift kick drift form
+const YOSHIDA_W1: f32 = 1.351_207_2; // 1 / (2 - 2^(1/3))
+const YOSHIDA_W0: f32 = -1.702_414_4; // -2^(1/3) * w1
+const YOSHIDA_DRIFTS: [f32; 4] = [
+    YOSHIDA_W1 * 0.5,
+    (YOSHIDA_W0 + YOSHIDA_W1) * 0.5,
+    (YOSHIDA_W0 + YOSHIDA_W1) * 0.5,
+    YOSHIDA_W1 * 0.5,
+];
+const YOSHIDA_KICKS: [f32; 3] = [YOSHIDA_W1, YOSHIDA_W0, YOSHIDA_W1];
 
 #[derive(Clone)]
 pub struct SimulationSpecs {
     dt: f32,
     sim_time: f32, // ms
     n_sub_steps: u32,
+    integrator: u32,
+    gravity_solver: u32,
+    theta: f32,
+    is_using_quadrupole: bool,
+    fmm_order: u32,
+    fmm_theta: f32,
+    pm_grid: u32,
+    is_pm_periodic: bool,
+    pm_split_scale: f32,
+    softening: Softening,
+    n_block_levels: u32,
+    block_rebuild_interval: u32,
+    timestep_accuracy: f32,
+    is_adaptive_timestep: bool,
+    courant_factor: f32,
+    min_timestep: f32,
+    max_timestep: f32,
     n_collision_steps: u32,
     n_update_cache_steps: u32,
     n_particles: u32,
     particle_distribution: u32,
     spawn_radius: f32,
     is_recording: bool,
+    boundary: Boundary,
+    collision_restitution: f32,
+    collision_friction: f32,
+    is_recording_spin: bool,
+    pattern_speed: f32,
+    is_recording_inertial: bool,
+    merge_velocity: f32,
+    merge_density: f32,
+    is_merge_conserving_spin: bool,
+    fragmentation: Fragmentation,
+    external_potentials: Vec<ExternalPotential>,
+    species: Vec<Species>,
+    species_counts: Vec<u32>,
+    gravity_matrix: InteractionMatrix,
+    collision_matrix: InteractionMatrix,
+    sph: Sph,
+    is_recording_gas: bool,
+    background_flow: BackgroundFlow,
+    cosmology: Cosmology,
+    post_newtonian: PostNewtonian,
+    sink_formation: SinkFormation,
 
     // dependent variables
     n_steps: u32,
@@ -32,12 +114,50 @@ impl SimulationSpecs {
             dt: 0.1,
             sim_time: 10.0,
             n_sub_steps: 5,
+            integrator: VERLET_INTEGRATOR,
+            gravity_solver: TREE_GRAVITY,
+            theta: THETA,
+            is_using_quadrupole: true,
+            fmm_order: FMM_ORDER,
+            fmm_theta: FMM_THETA,
+            pm_grid: PM_GRID,
+            is_pm_periodic: false,
+            pm_split_scale: PM_SPLIT_SCALE,
+            softening: Softening::new(PLUMMER_SOFTENING, EPS),
+            n_block_levels: 0,
+            block_rebuild_interval: 1,
+            timestep_accuracy: 0.025,
+            is_adaptive_timestep: true,
+            courant_factor: 0.5,
+            min_timestep: 0.000001,
+            max_timestep: 0.1,
             n_collision_steps: 3,
             n_update_cache_steps: 1,
             n_particles: 100,
             particle_distribution: UNIFORM_DISTRIBUTION,
             spawn_radius: 0.5,
             is_recording: false,
+            boundary: Boundary::square(1.0, WallMaterial::new(WALL_RESTITUTION, WALL_FRICTION)),
+            collision_restitution: COLLISION_RESTITUTION,
+            collision_friction: COLLISION_FRICTION,
+            is_recording_spin: false,
+            pattern_speed: 0.0,
+            is_recording_inertial: false,
+            merge_velocity: 0.0,
+            merge_density: 0.0,
+            is_merge_conserving_spin: true,
+            fragmentation: Fragmentation::disabled(),
+            external_potentials: Vec::new(),
+            species: vec![Species::default()],
+            species_counts: vec![0],
+            gravity_matrix: InteractionMatrix::new(),
+            collision_matrix: InteractionMatrix::new(),
+            sph: Sph::adiabatic(SPH_GAMMA),
+            is_recording_gas: false,
+            background_flow: BackgroundFlow::uniform(Vec2::zero()),
+            cosmology: Cosmology::disabled(),
+            post_newtonian: PostNewtonian::disabled(),
+            sink_formation: SinkFormation::disabled(),
 
             n_steps: (10.0 / 0.1) as u32,
             sub_step_dt: 0.1 / 5.0,
@@ -48,6 +168,162 @@ impl SimulationSpecs {
         return self.n_particles;
     }
 
+    // species 0 is the default one, it spawns n_particles. returns the index of the new species
+    pub fn add_species(&mut self, species: Species, n_particles: u32) -> u32 {
+        assert!(self.species.len() < MAX_SPECIES, "too many species");
+
+        self.species.push(species);
+        self.species_counts.push(n_particles);
+        return self.species.len() as u32 - 1;
+    }
+
+    pub fn set_default_species(&mut self, species: Species) {
+        self.species[0] = species;
+    }
+
+    // for the default species, the others carry their own
+    pub fn set_size_distribution(&mut self, size_distribution: SizeDistribution) {
+        self.species[0].set_size_distribution(size_distribution);
+    }
+
+    pub fn get_species(&self) -> &Vec<Species> {
+        return &self.species;
+    }
+
+    // how many particles of this species init_particles spawns
+    pub fn get_species_count(&self, species_i: usize) -> u32 {
+        if species_i == 0 {
+            return self.n_particles;
+        }
+        return self.species_counts[species_i];
+    }
+
+    // the species setters below take the index add_species returned, 0 for the default species.
+    // see the Species methods of the same name for what they do
+    pub fn set_species_density(&mut self, species_i: u32, density: f32) {
+        self.species[species_i as usize].set_density(density);
+    }
+
+    pub fn set_species_softening(&mut self, species_i: u32, softening: f32) {
+        self.species[species_i as usize].set_softening(softening);
+    }
+
+    pub fn set_species_gas(&mut self, species_i: u32, internal_energy: f32) {
+        self.species[species_i as usize].set_gas(internal_energy);
+    }
+
+    pub fn set_species_compact(&mut self, species_i: u32) {
+        self.species[species_i as usize].set_compact();
+    }
+
+    pub fn set_species_sink(&mut self, species_i: u32, accretion_radius: f32) {
+        self.species[species_i as usize].set_sink(accretion_radius);
+    }
+
+    pub fn set_species_tracer(&mut self, species_i: u32, is_colliding: bool) {
+        self.species[species_i as usize].set_tracer(is_colliding);
+    }
+
+    // one way, e.g. dust can feel the stars without the stars feeling the dust
+    pub fn set_gravity_interaction(&mut self, target: u32, source: u32, is_feeling: bool) {
+        self.gravity_matrix.set(target, source, is_feeling);
+    }
+
+    pub fn get_gravity_matrix(&self) -> &InteractionMatrix {
+        return &self.gravity_matrix;
+    }
+
+    // always both ways
+    pub fn set_collision_interaction(&mut self, species_a: u32, species_b: u32, is_colliding: bool) {
+        self.collision_matrix.set(species_a, species_b, is_colliding);
+        self.collision_matrix.set(species_b, species_a, is_colliding);
+    }
+
+    pub fn get_collision_matrix(&self) -> &InteractionMatrix {
+        return &self.collision_matrix;
+    }
+
+    // only matters once a species is set_gas
+    pub fn set_sph(&mut self, sph: Sph) {
+        self.sph = sph;
+    }
+
+    pub fn get_sph(&self) -> &Sph {
+        return &self.sph;
+    }
+
+    // FIXED_DRAG, EPSTEIN_DRAG or STOKES_DRAG, with the stopping time of a particle of the
+    // species radius. 0 turns it off again
+    pub fn set_drag(&mut self, species_i: u32, regime: u32, stopping_time: f32) {
+        self.species[species_i as usize].set_drag(Drag::new(regime, stopping_time, 0.0));
+    }
+
+    // what species with a drag are dragged along by, still air by default
+    pub fn set_background_flow(&mut self, background_flow: BackgroundFlow) {
+        self.background_flow = background_flow;
+    }
+
+    pub fn get_background_flow(&self) -> BackgroundFlow {
+        return self.background_flow;
+    }
+
+    // comoving run in a periodic box. also switches to periodic pm gravity without walls and to
+    // zel'dovich initial conditions, set those again afterwards to change them
+    pub fn set_cosmology(&mut self, cosmology: Cosmology) {
+        self.cosmology = cosmology;
+        self.gravity_solver = PM_GRAVITY;
+        self.is_pm_periodic = true;
+        self.boundary = Boundary::new();
+        self.particle_distribution = ZELDOVICH_DISTRIBUTION;
+        self.n_particles = cosmology.get_n_particles();
+    }
+
+    pub fn get_cosmology(&self) -> Cosmology {
+        return self.cosmology;
+    }
+
+    // relativistic corrections between particles of compact species, see Species::set_compact
+    pub fn set_post_newtonian(&mut self, post_newtonian: PostNewtonian) {
+        self.post_newtonian = post_newtonian;
+    }
+
+    // call after set_post_newtonian. both orders are on by default, turning one off is mostly
+    // for looking at the precession or the inspiral on its own
+    pub fn set_post_newtonian_orders(&mut self, is_using_1pn: bool, is_using_25pn: bool) {
+        self.post_newtonian.is_using_1pn = is_using_1pn;
+        self.post_newtonian.is_using_25pn = is_using_25pn;
+    }
+
+    pub fn get_post_newtonian(&self) -> PostNewtonian {
+        return self.post_newtonian;
+    }
+
+    // sinks that appear where it gets dense, see Species::set_sink for the ones from the start
+    pub fn set_sink_formation(&mut self, sink_formation: SinkFormation) {
+        self.sink_formation = sink_formation;
+    }
+
+    // call after set_sink_formation. the density of a would be sink is taken over its
+    // n_neighbours closest particles, 16 by default
+    pub fn set_sink_neighbours(&mut self, n_neighbours: u32) {
+        self.sink_formation.n_neighbours = n_neighbours;
+    }
+
+    // call after set_sink_formation. species that can turn into sinks, one bit each, all of
+    // them by default
+    pub fn set_sink_sources(&mut self, sources: u32) {
+        self.sink_formation.sources = sources;
+    }
+
+    pub fn get_sink_formation(&self) -> SinkFormation {
+        return self.sink_formation;
+    }
+
+    // adds density, pressure and temperature to every particle in the recording, 0 if not gas
+    pub fn set_recording_gas(&mut self, rec: bool) {
+        self.is_recording_gas = rec;
+    }
+
     pub fn set_distribution(&mut self, new_dist: u32) {
         self.particle_distribution = new_dist;
     }
@@ -68,6 +344,272 @@ impl SimulationSpecs {
         return self.particle_distribution;
     }
 
+    pub fn set_boundary(&mut self, boundary: Boundary) {
+        self.boundary = boundary;
+    }
+
+    pub fn get_boundary(&self) -> &Boundary {
+        return &self.boundary;
+    }
+
+    // applies to every wall of the current boundary
+    pub fn set_wall_restitution(&mut self, restitution: f32) {
+        self.boundary.set_restitution(restitution);
+    }
+
+    pub fn set_wall_friction(&mut self, friction: f32) {
+        self.boundary.set_friction(friction);
+    }
+
+    // 1.0 is an elastic gas, 0.0 makes colliding particles stick
+    pub fn set_collision_restitution(&mut self, restitution: f32) {
+        self.collision_restitution = restitution;
+    }
+
+    pub fn get_collision_restitution(&self) -> f32 {
+        return self.collision_restitution;
+    }
+
+    // coulomb coefficient between particles, anything above 0 makes them spin
+    pub fn set_collision_friction(&mut self, friction: f32) {
+        self.collision_friction = friction;
+    }
+
+    pub fn get_collision_friction(&self) -> f32 {
+        return self.collision_friction;
+    }
+
+    pub fn set_recording_spin(&mut self, rec: bool) {
+        self.is_recording_spin = rec;
+    }
+
+    // simulate in a frame rotating counter clockwise around the origin, e.g. with a bar.
+    // the integrator adds the coriolis and centrifugal terms
+    pub fn set_pattern_speed(&mut self, pattern_speed: f32) {
+        self.pattern_speed = pattern_speed;
+    }
+
+    pub fn get_pattern_speed(&self) -> f32 {
+        return self.pattern_speed;
+    }
+
+    // record positions and speeds turned back into the inertial frame instead of the rotating one
+    pub fn set_recording_inertial(&mut self, rec: bool) {
+        self.is_recording_inertial = rec;
+    }
+
+    // colliding particles slower than this (relative, per unit time) merge into one. 0 turns it off
+    pub fn set_merge_velocity(&mut self, velocity: f32) {
+        self.merge_velocity = velocity;
+    }
+
+    pub fn get_merge_velocity(&self) -> f32 {
+        return self.merge_velocity;
+    }
+
+    // density used for the radius of merged particles, 0 keeps the parents' volume
+    pub fn set_merge_density(&mut self, density: f32) {
+        self.merge_density = density;
+    }
+
+    pub fn get_merge_density(&self) -> f32 {
+        return self.merge_density;
+    }
+
+    // when off only the parents' spins are kept, not the orbital part of the pair
+    pub fn set_merge_conserving_spin(&mut self, conserve: bool) {
+        self.is_merge_conserving_spin = conserve;
+    }
+
+    pub fn is_merge_conserving_spin(&self) -> bool {
+        return self.is_merge_conserving_spin;
+    }
+
+    pub fn set_fragmentation(&mut self, fragmentation: Fragmentation) {
+        self.fragmentation = fragmentation;
+    }
+
+    pub fn get_fragmentation(&self) -> &Fragmentation {
+        return &self.fragmentation;
+    }
+
+    // they all add up, e.g. a disk in a halo around a central black hole. returns the index
+    // of the new potential
+    pub fn add_external_potential(&mut self, potential: ExternalPotential) -> u32 {
+        self.external_potentials.push(potential);
+        return self.external_potentials.len() as u32 - 1;
+    }
+
+    pub fn add_uniform_field(&mut self, field: Vec2) -> u32 {
+        return self.add_external_potential(ExternalPotential::uniform(field));
+    }
+
+    pub fn add_point_mass_potential(&mut self, center: Vec2, mass: f32) -> u32 {
+        return self.add_external_potential(ExternalPotential::point_mass(center, mass));
+    }
+
+    pub fn add_kepler_potential(&mut self, center: Vec2, mass: f32, softening: f32) -> u32 {
+        return self.add_external_potential(ExternalPotential::kepler(center, mass, softening));
+    }
+
+    pub fn add_logarithmic_potential(
+        &mut self,
+        center: Vec2,
+        circular_vel: f32,
+        core_radius: f32,
+    ) -> u32 {
+        return self.add_external_potential(ExternalPotential::logarithmic(
+            center,
+            circular_vel,
+            core_radius,
+        ));
+    }
+
+    pub fn add_nfw_potential(&mut self, center: Vec2, mass: f32, scale_radius: f32) -> u32 {
+        return self.add_external_potential(ExternalPotential::nfw(center, mass, scale_radius));
+    }
+
+    // false leaves the potential out of the diagnostics energy
+    pub fn set_external_potential_in_energy(&mut self, potential_i: u32, is_in_energy: bool) {
+        self.external_potentials[potential_i as usize].set_in_energy(is_in_energy);
+    }
+
+    pub fn clear_external_potentials(&mut self) {
+        self.external_potentials.clear();
+    }
+
+    pub fn get_external_potentials(&self) -> &Vec<ExternalPotential> {
+        return &self.external_potentials;
+    }
+
+    pub fn set_integrator(&mut self, integrator: u32) {
+        self.integrator = integrator;
+    }
+
+    pub fn get_integrator(&self) -> u32 {
+        return self.integrator;
+    }
+
+    pub fn set_gravity_solver(&mut self, solver: u32) {
+        self.gravity_solver = solver;
+    }
+
+    pub fn get_gravity_solver(&self) -> u32 {
+        return self.gravity_solver;
+    }
+
+    // barnes-hut opening angle, with quadrupoles on a larger theta gives the same accuracy
+    pub fn set_theta(&mut self, theta: f32) {
+        self.theta = theta;
+    }
+
+    pub fn get_theta(&self) -> f32 {
+        return self.theta;
+    }
+
+    pub fn set_quadrupole(&mut self, is_using_quadrupole: bool) {
+        self.is_using_quadrupole = is_using_quadrupole;
+    }
+
+    pub fn is_using_quadrupole(&self) -> bool {
+        return self.is_using_quadrupole;
+    }
+
+    // number of terms kept in each direction of the fmm expansions
+    pub fn set_fmm_order(&mut self, order: u32) {
+        self.fmm_order = order;
+    }
+
+    pub fn get_fmm_order(&self) -> u32 {
+        return self.fmm_order;
+    }
+
+    pub fn set_fmm_theta(&mut self, theta: f32) {
+        self.fmm_theta = theta;
+    }
+
+    pub fn get_fmm_theta(&self) -> f32 {
+        return self.fmm_theta;
+    }
+
+    // cells per side of the mesh covering [-1, 1], has to be a power of two
+    pub fn set_pm_grid(&mut self, n_cells: u32) {
+        self.pm_grid = n_cells;
+    }
+
+    pub fn get_pm_grid(&self) -> u32 {
+        return self.pm_grid;
+    }
+
+    // wraps the mesh instead of zero padding it. the tree part of treepm never wraps
+    pub fn set_pm_periodic(&mut self, is_periodic: bool) {
+        self.is_pm_periodic = is_periodic;
+    }
+
+    pub fn is_pm_periodic(&self) -> bool {
+        return self.is_pm_periodic;
+    }
+
+    // treepm split radius in mesh cells
+    pub fn set_pm_split_scale(&mut self, split_scale: f32) {
+        self.pm_split_scale = split_scale;
+    }
+
+    pub fn get_pm_split_scale(&self) -> f32 {
+        return self.pm_split_scale;
+    }
+
+    // kernel is one of the *_SOFTENING constants, the length is the plummer equivalent one
+    pub fn set_softening(&mut self, kernel: u32, length: f32) {
+        self.softening = Softening::new(kernel, length);
+    }
+
+    // call after set_softening. every frame each particle gets factor times the distance to its
+    // n_neighbours-th closest particle, kept between the softening length and max_length
+    pub fn set_adaptive_softening(&mut self, n_neighbours: u32, factor: f32, max_length: f32) {
+        self.softening.set_adaptive(n_neighbours, factor, max_length);
+    }
+
+    pub fn get_softening(&self) -> Softening {
+        return self.softening;
+    }
+
+    // hierarchical block timesteps, particles step with sub_step_dt / 2^bin for bins up to
+    // n_levels. 0 turns them off, they always integrate with kick drift kick
+    pub fn set_block_levels(&mut self, n_levels: u32) {
+        self.n_block_levels = n_levels;
+    }
+
+    // how many of the smallest block steps between full quadtree rebuilds, it is refit in between
+    pub fn set_block_rebuild_interval(&mut self, interval: u32) {
+        self.block_rebuild_interval = interval.max(1);
+    }
+
+    // eta in dt = sqrt(2 * eta * softening / |a|)
+    pub fn set_timestep_accuracy(&mut self, accuracy: f32) {
+        self.timestep_accuracy = accuracy;
+    }
+
+    // picks every sub step from the accelerations and speeds, frames still come out every dt.
+    // on by default, turning it off goes back to n_sub_steps fixed sub steps per frame
+    pub fn set_adaptive_timestep(&mut self, adaptive: bool) {
+        self.is_adaptive_timestep = adaptive;
+    }
+
+    // dt <= courant * radius / speed
+    pub fn set_courant_factor(&mut self, courant: f32) {
+        self.courant_factor = courant;
+    }
+
+    pub fn set_timestep_bounds(&mut self, min_dt: f32, max_dt: f32) {
+        self.min_timestep = min_dt;
+        self.max_timestep = max_dt;
+    }
+
+    pub fn get_sub_step_dt(&self) -> f32 {
+        return self.sub_step_dt;
+    }
+
     pub fn update_dependents(&mut self) {
         self.n_steps = (self.sim_time / self.dt) as u32;
         self.sub_step_dt = self.dt / self.n_sub_steps as f32;
@@ -107,6 +649,7 @@ impl SimulationSpecs {
         self.sim_time = sim_time;
         self.update_dependents();
     }
+    // only used with set_adaptive_timestep(false)
     pub fn set_n_sub_steps(&mut self, sub_steps: u32) {
         self.n_sub_steps = sub_steps;
         self.update_dependents();
@@ -115,42 +658,171 @@ impl SimulationSpecs {
 
 pub struct SimulationRecorder {
     data: Vec<RecorderStep>,
+    diagnostics: Vec<DiagnosticsStep>,
+    merge_events: Vec<MergeEvent>,
+    fragment_events: Vec<FragmentEvent>,
+    accretion_events: Vec<AccretionEvent>,
+    pub is_recording_spin: bool,
+    pub is_recording_inertial: bool,
+    pub is_recording_gas: bool,
+    pub species: Vec<Species>,
 }
 
 impl SimulationRecorder {
     pub fn new() -> SimulationRecorder {
-        return SimulationRecorder { data: Vec::new() };
+        return SimulationRecorder {
+            data: Vec::new(),
+            diagnostics: Vec::new(),
+            merge_events: Vec::new(),
+            fragment_events: Vec::new(),
+            accretion_events: Vec::new(),
+            is_recording_spin: false,
+            is_recording_inertial: false,
+            is_recording_gas: false,
+            species: Vec::new(),
+        };
     }
 
     pub fn record_step(&mut self, container: &mut Container, n_sub_steps: u32) {
         for particle in &mut container.particles {
             particle.n_total_collisions /= n_sub_steps;
         }
-        self.data.push(RecorderStep::new(&container));
+        self.data
+            .push(RecorderStep::new(container, self.is_recording_inertial));
         for particle in &mut container.particles {
             particle.reset_collisions();
         }
+
+        self.diagnostics.push(DiagnosticsStep::new(
+            container.time,
+            n_sub_steps,
+            container.kinetic_energy(),
+            container.potential_energy(),
+            container.thermal_energy(),
+            container.get_redshift(),
+            &container.diagnostics,
+        ));
+        container.diagnostics.reset();
+
+        self.merge_events.append(&mut container.merge_events);
+        self.fragment_events
+            .append(&mut container.fragment_events);
+        self.accretion_events
+            .append(&mut container.accretion_events);
     }
 
-    pub fn export_recording(&self, path: &str) {
-        let mut recording_string = String::new();
+    pub fn export_merge_events(&self, path: &str) {
+        let mut merge_string = String::new();
+
+        merge_string.push_str(MergeEvent::HEADER);
+        merge_string.push('\n');
+        for event in &self.merge_events {
+            merge_string.push_str(&event.to_line());
+            merge_string.push('\n');
+        }
+
+        std::fs::write(path, merge_string).expect("Unable to write file");
+    }
+
+    pub fn export_fragment_events(&self, path: &str) {
+        let mut fragment_string = String::new();
+
+        fragment_string.push_str(FragmentEvent::HEADER);
+        fragment_string.push('\n');
+        for event in &self.fragment_events {
+            fragment_string.push_str(&event.to_line());
+            fragment_string.push('\n');
+        }
+
+        std::fs::write(path, fragment_string).expect("Unable to write file");
+    }
+
+    // grouped by sink so every sink's history is one block, in time order within it
+    pub fn export_accretion_events(&self, path: &str) {
+        let mut accretion_string = String::new();
+
+        let mut events: Vec<&AccretionEvent> = self.accretion_events.iter().collect();
+        events.sort_by_key(|event| event.sink_id);
+
+        accretion_string.push_str(AccretionEvent::HEADER);
+        accretion_string.push('\n');
+        for event in events {
+            accretion_string.push_str(&event.to_line());
+            accretion_string.push('\n');
+        }
+
+        std::fs::write(path, accretion_string).expect("Unable to write file");
+    }
+
+    // the renderer colors by species with this
+    pub fn export_species(&self, path: &str) {
+        let mut species_string = String::new();
+
+        for (species_i, species) in self.species.iter().enumerate() {
+            species_string.push_str(&species.to_line(species_i));
+            species_string.push('\n');
+        }
+
+        std::fs::write(path, species_string).expect("Unable to write file");
+    }
+
+    pub fn export_diagnostics(&self, path: &str) {
+        let mut diagnostics_string = String::new();
+
+        diagnostics_string.push_str(DiagnosticsStep::HEADER);
+        diagnostics_string.push('\n');
+        for step in &self.diagnostics {
+            diagnostics_string.push_str(&step.to_line());
+            diagnostics_string.push('\n');
+        }
 
+        std::fs::write(path, diagnostics_string).expect("Unable to write file");
+    }
+
+    pub fn export_recording(&self, path: &str) {
         println!("EXPORTING RECORDING");
+        self.export_particle_data(path, false);
+        println!("\nSIM END");
+    }
+
+    // same format as the recording, one line per frame
+    pub fn export_tracers(&self, path: &str) {
+        println!("EXPORTING TRACERS");
+        self.export_particle_data(path, true);
+        println!();
+    }
+
+    fn export_particle_data(&self, path: &str, is_tracers: bool) {
+        let mut recording_string = String::new();
 
         let mut progress_bar = ProgressBar::new(self.data.len() as u32);
         progress_bar.refresh();
         for particle_step in &self.data {
-            for data in &particle_step.particle_data {
+            let particle_data = if is_tracers {
+                &particle_step.tracer_data
+            } else {
+                &particle_step.particle_data
+            };
+            for data in particle_data {
                 recording_string.push_str(&format!(
-                    "{} {} {} {},",
-                    data.position.x, data.position.y, data.speed, data.n_collisions
+                    "{} {} {} {} {}",
+                    data.position.x, data.position.y, data.speed, data.n_collisions, data.species
                 ));
+                if self.is_recording_spin {
+                    recording_string.push_str(&format!(" {}", data.spin));
+                }
+                if self.is_recording_gas {
+                    recording_string.push_str(&format!(
+                        " {} {} {}",
+                        data.gas_density, data.pressure, data.temperature
+                    ));
+                }
+                recording_string.push(',');
             }
-            recording_string.push_str("\n");
+            recording_string.push('\n');
             progress_bar.increment();
             progress_bar.refresh();
         }
-        println!("\nSIM END");
 
         std::fs::write(path, recording_string).expect("Unable to write file");
     }
@@ -158,16 +830,36 @@ impl SimulationRecorder {
 
 pub struct RecorderStep {
     particle_data: Vec<ParticleData>,
+    // kept apart so the probes do not get mixed into the actual particles
+    tracer_data: Vec<ParticleData>,
 }
 
 impl RecorderStep {
-    pub fn new(container: &Container) -> RecorderStep {
+    pub fn new(container: &Container, is_inertial: bool) -> RecorderStep {
         let mut particle_data: Vec<ParticleData> = Vec::new();
+        let mut tracer_data: Vec<ParticleData> = Vec::new();
         for particle in &container.particles {
-            particle_data.push(ParticleData::new(&particle));
+            let data = if particle.is_tracer {
+                &mut tracer_data
+            } else {
+                &mut particle_data
+            };
+            if is_inertial {
+                data.push(ParticleData::inertial(
+                    particle,
+                    container.pattern_speed,
+                    container.time,
+                ));
+            } else {
+                data.push(ParticleData::new(particle));
+            }
+            data.last_mut().unwrap().temperature = container.sph.temperature(particle);
         }
 
-        return RecorderStep { particle_data };
+        return RecorderStep {
+            particle_data,
+            tracer_data,
+        };
     }
 }
 
@@ -180,51 +872,269 @@ pub struct Simulation {
 
 impl Simulation {
     pub fn construct(sim_specs: &SimulationSpecs) -> Simulation {
+        let mut sim_recorder = SimulationRecorder::new();
+        sim_recorder.is_recording_spin = sim_specs.is_recording_spin;
+        sim_recorder.is_recording_inertial = sim_specs.is_recording_inertial;
+        sim_recorder.is_recording_gas = sim_specs.is_recording_gas;
+        sim_recorder.species = sim_specs.species.clone();
+
         return Simulation {
             container: Container::new(),
             sim_info: sim_specs.clone(),
-            sim_recorder: SimulationRecorder::new(),
+            sim_recorder,
         };
     }
 
-    pub fn run(&mut self) {
-        println!("SIM START");
+    // leaves the quadtree built, the collisions use it afterwards
+    pub fn compute_forces(&mut self) {
+        self.container.reset_acc();
 
-        self.container.init_particles(&self.sim_info);
+        self.container.rebuild_quadtree();
 
-        let mut progress_bar = ProgressBar::new(self.sim_info.n_steps);
-        progress_bar.refresh();
+        self.compute_gravity();
+    }
 
-        for sim_step_i in 0..self.sim_info.n_steps {
+    // expects the quadtree to be ready, only touches the active particles
+    pub fn compute_gravity(&mut self) {
+        let is_pairwise = self.sim_info.integrator == HERMITE_INTEGRATOR
+            || self.sim_info.gravity_solver == DIRECT_GRAVITY;
 
-            self.container.particles.sort_unstable_by_key(|p| morton_key(p.pos));
-            for _sub_step_i in 0..self.sim_info.n_sub_steps {
-                // let t1 = Instant::now();
-                self.container
-                    .integrate_particles(self.sim_info.sub_step_dt);
+        if is_pairwise || self.container.is_gravity_all_to_all() {
+            self.solve_gravity();
+        } else {
+            // the tree and the mesh can not tell species apart so every source species gets
+            // a pass of its own with only its particles in them
+            for species_i in 0..self.container.species.len() as u32 {
+                if !self.container.is_gravity_source(species_i)
+                    || !self.container.has_particles(species_i)
+                {
+                    continue;
+                }
+                self.container.set_gravity_sources(1 << species_i);
+                self.solve_gravity();
+            }
+            self.container.set_gravity_sources(u32::MAX);
+        }
 
-                self.container
-                    .container_collisions(self.sim_info.sub_step_dt);
+        if self.container.cosmology.is_enabled() {
+            self.container.scale_comoving_gravity();
+        }
+        if self.container.post_newtonian.is_enabled() {
+            self.container.post_newtonian_forces();
+        }
+
+        self.container.apply_external_potentials();
+        self.container.hydro_forces();
+        // block steps kick like the leapfrog whatever the integrator, the kicks do coriolis then
+        let is_coriolis_in_acc = self.sim_info.integrator == HERMITE_INTEGRATOR
+            && self.sim_info.n_block_levels == 0;
+        self.container.apply_frame_forces(is_coriolis_in_acc);
+    }
+
+    // the direct sums check the gravity matrix pair by pair
+    fn solve_gravity(&mut self) {
+        if self.sim_info.integrator == HERMITE_INTEGRATOR {
+            self.container.interparticle_gravity_quadratic();
+            self.container.interparticle_jerk_quadratic();
+        } else if self.sim_info.gravity_solver == DIRECT_GRAVITY {
+            self.container.interparticle_gravity_quadratic();
+        } else if self.sim_info.gravity_solver == FMM_GRAVITY {
+            self.container.interparticle_gravity_fmm();
+        } else if self.sim_info.gravity_solver == PM_GRAVITY {
+            self.container.interparticle_gravity_pm();
+        } else if self.sim_info.gravity_solver == TREE_PM_GRAVITY {
+            self.container.interparticle_gravity_tree_pm();
+        } else {
+            self.container.interparticle_gravity();
+        }
+    }
+
+    // one sub step split into 2^n_levels micro steps. everything drifts every micro step but
+    // only the particles at the end of their own step get new forces
+    pub fn block_sub_step(&mut self, dt: f32) {
+        let n_levels = self.sim_info.n_block_levels;
+        let n_micro_steps = 1 << n_levels;
+        let micro_dt = dt / n_micro_steps as f32;
+
+        self.container.start_step();
+
+        // everyone is synchronised at the start of a sub step
+        self.container.set_all_active();
+        self.container
+            .assign_time_bins(0, n_levels, dt, self.sim_info.timestep_accuracy);
+
+        for micro_step_i in 0..n_micro_steps {
+            self.container.kick_bins(micro_step_i, n_levels, dt);
+            self.container.drift_particles(micro_dt);
+            self.container.container_collisions(micro_dt);
+
+            if (micro_step_i + 1).is_multiple_of(self.sim_info.block_rebuild_interval) {
+                self.container.rebuild_quadtree();
+            } else {
+                self.container.quadtree.refit(&self.container.particles);
+            }
+
+            self.container
+                .mark_active_bins(micro_step_i + 1, n_levels);
+            self.container.reset_acc();
+            self.container.velocity_lag = dt * 0.5;
+            self.compute_gravity();
+            self.container.velocity_lag = 0.0;
+
+            self.container.kick_bins(micro_step_i + 1, n_levels, dt);
+            self.container.assign_time_bins(
+                micro_step_i + 1,
+                n_levels,
+                dt,
+                self.sim_info.timestep_accuracy,
+            );
+        }
+
+        self.container.set_all_active();
+        self.container.rebuild_quadtree();
+    }
+
+    pub fn sub_step(&mut self, dt: f32) {
+        // the forces of this step already see the new scale factor
+        if self.container.cosmology.is_enabled() {
+            self.container.advance_scale_factor(dt);
+        }
 
-                self.container.construct_quadtree();
-                self.container.quadtree.propogate_mass();
+        if self.sim_info.n_block_levels > 0 {
+            self.block_sub_step(dt);
+            self.finish_sub_step(dt);
+            return;
+        }
 
-                self.container.interparticle_gravity();
+        self.container.start_step();
 
-                self.container.particle_collision(
-                    self.sim_info.n_collision_steps,
-                    self.sim_info.n_update_cache_steps,
-                    self.sim_info.sub_step_dt,
-                );
+        let integrator = self.sim_info.integrator;
+        if integrator == LEAPFROG_INTEGRATOR {
+            // kick drift kick, the forces are evaluated at the synchronised positions
+            self.container.kick_particles(dt * 0.5);
+            self.container.drift_particles(dt);
+            self.container.container_collisions(dt);
 
+            self.container.velocity_lag = dt * 0.5;
+            self.compute_forces();
+            self.container.velocity_lag = 0.0;
+            self.container.kick_particles(dt * 0.5);
+        } else if integrator == YOSHIDA_INTEGRATOR {
+            for stage_i in 0..3 {
                 self.container
-                    .container_collisions(self.sim_info.sub_step_dt);
-                // println!("{:?}", t1.elapsed());
+                    .drift_particles(dt * YOSHIDA_DRIFTS[stage_i]);
+                self.compute_forces();
+                self.container.kick_particles(dt * YOSHIDA_KICKS[stage_i]);
+            }
+            self.container.drift_particles(dt * YOSHIDA_DRIFTS[3]);
+            self.container.container_collisions(dt);
+
+            // the last force pass was done before the final drift
+            self.container.rebuild_quadtree();
+        } else if integrator == HERMITE_INTEGRATOR {
+            self.container.hermite_predict_particles(dt);
+            self.compute_forces();
+            self.container.hermite_correct_particles(dt);
+            self.container.container_collisions(dt);
+
+            self.container.rebuild_quadtree();
+        } else {
+            self.container.integrate_particles(dt);
+            self.container.container_collisions(dt);
+
+            self.compute_forces();
+        }
+
+        self.finish_sub_step(dt);
+    }
+
+    pub fn finish_sub_step(&mut self, dt: f32) {
+        if self.container.is_drag_enabled() {
+            self.container.apply_drag(dt);
+        }
+        if self.container.cosmology.is_enabled() {
+            self.container.hubble_drag();
+        }
+
+        self.container.particle_collision(
+            self.sim_info.n_collision_steps,
+            self.sim_info.n_update_cache_steps,
+            dt,
+        );
+
+        self.container.container_collisions(dt);
+
+        if self.container.is_using_sinks() {
+            if self.container.sink_formation.is_enabled() {
+                self.container.form_sinks();
+            }
+            self.container.accrete_onto_sinks();
+        }
+
+        self.container.time += dt;
+    }
+
+    // sub steps until exactly one frame (dt) has passed, the last one gets cut short so the
+    // recorder keeps a fixed cadence. returns how many sub steps it took
+    pub fn adaptive_frame_step(&mut self) -> u32 {
+        let frame_dt = self.sim_info.dt;
+        let mut time_left = frame_dt;
+        let mut n_sub_steps = 0;
+
+        while time_left > frame_dt * 0.000001 {
+            let mut dt = adaptive_timestep(
+                &self.container.particles,
+                &self.sim_info.softening,
+                self.sim_info.timestep_accuracy,
+                self.sim_info.courant_factor,
+                self.sim_info.min_timestep,
+                self.sim_info.max_timestep,
+            );
+
+            // no point leaving a sliver of a step for the end of the frame
+            if dt * 1.001 >= time_left {
+                dt = time_left;
+            }
+
+            self.sub_step(dt);
+            time_left -= dt;
+            n_sub_steps += 1;
+        }
+
+        return n_sub_steps;
+    }
+
+    pub fn run(&mut self) {
+        println!("SIM START");
+
+        self.container.init_particles(&self.sim_info);
+        self.container.update_adaptive_softening();
+        self.compute_forces();
+
+        let mut progress_bar = ProgressBar::new(self.sim_info.n_steps);
+        progress_bar.refresh();
+
+        for sim_step_i in 0..self.sim_info.n_steps {
+            self.container
+                .particles
+                .sort_unstable_by_key(|p| morton_key(p.pos));
+            if self.sim_info.softening.is_adaptive() {
+                self.container.update_adaptive_softening();
             }
+            let n_sub_steps = if self.sim_info.is_adaptive_timestep {
+                self.adaptive_frame_step()
+            } else {
+                for _sub_step_i in 0..self.sim_info.n_sub_steps {
+                    // let t1 = Instant::now();
+                    self.sub_step(self.sim_info.sub_step_dt);
+                    // println!("{:?}", t1.elapsed());
+                }
+                self.sim_info.n_sub_steps
+            };
 
             if self.sim_info.is_recording {
                 self.sim_recorder
-                    .record_step(&mut self.container, self.sim_info.n_sub_steps);
+                    .record_step(&mut self.container, n_sub_steps);
             }
 
             progress_bar.increment();
@@ -0,0 +1,54 @@
+use crate::constants::GRAVITY_CONST;
+use crate::particle::Particle;
+
+// turns particles into sinks once their surroundings get dense enough. the density is the gas
+// density for sph particles and n_neighbours particle masses over the circle out to the
+// n_neighbours-th neighbour for everything else, both per unit area. a threshold of 0 turns
+// formation off, sinks can still come from Species::set_sink
+#[derive(Clone, Copy)]
+pub struct SinkFormation {
+    pub density_threshold: f32,
+    // what the new sinks get
+    pub accretion_radius: f32,
+    pub n_neighbours: u32,
+    // species that can turn into sinks, one bit each
+    pub sources: u32,
+}
+
+impl SinkFormation {
+    pub fn new(density_threshold: f32, accretion_radius: f32) -> SinkFormation {
+        return SinkFormation {
+            density_threshold,
+            accretion_radius,
+            n_neighbours: 16,
+            sources: u32::MAX,
+        };
+    }
+
+    pub fn disabled() -> SinkFormation {
+        return SinkFormation::new(0.0, 0.0);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        return self.density_threshold > 0.0;
+    }
+
+    pub fn is_source(&self, species_i: u32) -> bool {
+        return self.sources & (1 << species_i) != 0;
+    }
+}
+
+// relative orbital energy per unit mass of particle around sink, below 0 once it can not
+// get away anymore. f32::MAX outside the accretion radius
+pub fn accretion_energy(sink: &Particle, particle: &Particle) -> f32 {
+    let distance = (particle.pos - sink.pos).length();
+    if distance >= sink.accretion_radius {
+        return f32::MAX;
+    }
+    if distance == 0.0 {
+        return f32::MIN;
+    }
+
+    let rel_vel = particle.get_vel() - sink.get_vel();
+    return 0.5 * rel_vel.length_squared() - GRAVITY_CONST * (sink.mass + particle.mass) / distance;
+}
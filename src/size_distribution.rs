@@ -0,0 +1,130 @@
+use rand::Rng;
+use rand_distr::StandardNormal;
+
+// every particle gets the species radius and mass
+pub const FIXED_SIZE: u32 = 0;
+// dN/dx ~ x^-exponent between min and max
+pub const POWER_LAW_SIZE: u32 = 1;
+// ln x is normal around ln median with width sigma
+pub const LOG_NORMAL_SIZE: u32 = 2;
+// a handful of sizes picked with relative weights
+pub const DISCRETE_SIZE: u32 = 3;
+
+// what the initial particles of a species draw their size from. the drawn value is a radius
+// unless is_mass is set, the other one follows from the species density
+#[derive(Clone)]
+pub struct SizeDistribution {
+    pub kind: u32,
+    pub is_mass: bool,
+
+    pub min: f32,
+    pub max: f32,
+    pub exponent: f32,
+
+    pub median: f32,
+    pub sigma: f32,
+
+    pub values: Vec<f32>,
+    pub weights: Vec<f32>,
+}
+
+impl SizeDistribution {
+    pub fn fixed() -> SizeDistribution {
+        return SizeDistribution {
+            kind: FIXED_SIZE,
+            is_mass: false,
+            min: 0.0,
+            max: 0.0,
+            exponent: 0.0,
+            median: 0.0,
+            sigma: 0.0,
+            values: Vec::new(),
+            weights: Vec::new(),
+        };
+    }
+
+    pub fn power_law(min: f32, max: f32, exponent: f32) -> SizeDistribution {
+        let mut distribution = SizeDistribution::fixed();
+        distribution.kind = POWER_LAW_SIZE;
+        distribution.min = min;
+        distribution.max = max;
+        distribution.exponent = exponent;
+        return distribution;
+    }
+
+    pub fn log_normal(median: f32, sigma: f32) -> SizeDistribution {
+        let mut distribution = SizeDistribution::fixed();
+        distribution.kind = LOG_NORMAL_SIZE;
+        distribution.median = median;
+        distribution.sigma = sigma;
+        return distribution;
+    }
+
+    // weights are relative, they do not have to sum to 1
+    pub fn discrete(values: &[f32], weights: &[f32]) -> SizeDistribution {
+        assert!(
+            !values.is_empty(),
+            "discrete size distribution without values"
+        );
+        assert!(
+            values.len() == weights.len(),
+            "discrete size distribution needs one weight per value"
+        );
+        assert!(
+            weights.iter().all(|weight| *weight >= 0.0),
+            "discrete size distribution with a negative weight"
+        );
+        assert!(
+            weights.iter().sum::<f32>() > 0.0,
+            "discrete size distribution weights sum to 0"
+        );
+
+        let mut distribution = SizeDistribution::fixed();
+        distribution.kind = DISCRETE_SIZE;
+        distribution.values = values.to_vec();
+        distribution.weights = weights.to_vec();
+        return distribution;
+    }
+
+    // draw masses instead of radii
+    pub fn set_mass(&mut self, is_mass: bool) {
+        self.is_mass = is_mass;
+    }
+
+    pub fn is_fixed(&self) -> bool {
+        return self.kind == FIXED_SIZE;
+    }
+
+    // only for the non fixed kinds
+    pub fn sample(&self) -> f32 {
+        let mut rng = rand::rng();
+
+        if self.kind == POWER_LAW_SIZE {
+            // inverse transform, same as the fragment masses
+            let u: f32 = rng.random_range(0.0..1.0);
+            let power = 1.0 - self.exponent;
+            if power.abs() < 1e-6 {
+                return self.min * (self.max / self.min).powf(u);
+            }
+
+            let lower = self.min.powf(power);
+            let upper = self.max.powf(power);
+            return ((upper - lower) * u + lower).powf(1.0 / power);
+        } else if self.kind == LOG_NORMAL_SIZE {
+            let z: f32 = rng.sample(StandardNormal);
+            return self.median * (self.sigma * z).exp();
+        } else if self.kind == DISCRETE_SIZE {
+            let total: f32 = self.weights.iter().sum();
+            let mut u: f32 = rng.random_range(0.0..total);
+            for (value_i, weight) in self.weights.iter().enumerate() {
+                if u < *weight {
+                    return self.values[value_i];
+                }
+                u -= weight;
+            }
+            return self.values[self.values.len() - 1];
+        }
+
+        return 0.0;
+    }
+}
@@ -0,0 +1,259 @@
+use crate::constants::EPS;
+
+// plain 1/r, pairs at the same spot are skipped
+pub const NO_SOFTENING: u32 = 0;
+// 1/sqrt(r^2 + eps^2)
+pub const PLUMMER_SOFTENING: u32 = 1;
+// cubic spline with compact support, exact 1/r beyond 2.8 eps
+pub const SPLINE_SOFTENING: u32 = 2;
+
+// the spline reaches 1/r at this many softening lengths, which keeps the central potential the
+// same as plummer with the same length
+const SPLINE_SUPPORT: f32 = 2.8;
+// plummer never reaches 1/r, this far out the force is 0.2% short of it. at the spline support
+// it would still be 16% short
+const PLUMMER_SUPPORT: f32 = 30.0;
+
+// every gravity path goes through here so they all agree on the kernel. the factors are per
+// unit mass without G: force = delta * m * force_factor, potential = -m * potential_factor.
+// every particle carries its own length and a pair uses the larger of the two
+#[derive(Clone, Copy)]
+pub struct Softening {
+    kernel: u32,
+    // given to every particle that does not set its own, the lower limit when adaptive
+    length: f32,
+
+    // adaptive lengths are neighbour_factor times the distance to the n_neighbours-th closest
+    // particle, 0 neighbours keeps the lengths fixed
+    n_neighbours: u32,
+    neighbour_factor: f32,
+    max_length: f32,
+}
+
+impl Softening {
+    pub fn new(kernel: u32, length: f32) -> Softening {
+        return Softening {
+            kernel,
+            length,
+            n_neighbours: 0,
+            neighbour_factor: 0.0,
+            max_length: length,
+        };
+    }
+
+    pub fn set_adaptive(&mut self, n_neighbours: u32, neighbour_factor: f32, max_length: f32) {
+        self.n_neighbours = n_neighbours;
+        self.neighbour_factor = neighbour_factor;
+        self.max_length = max_length;
+    }
+
+    pub fn is_adaptive(&self) -> bool {
+        return self.n_neighbours > 0;
+    }
+
+    pub fn get_n_neighbours(&self) -> u32 {
+        return self.n_neighbours;
+    }
+
+    pub fn adaptive_length(&self, neighbour_distance: f32) -> f32 {
+        return (self.neighbour_factor * neighbour_distance).clamp(self.length, self.max_length);
+    }
+
+    pub fn get_length(&self) -> f32 {
+        return self.length;
+    }
+
+    // length scale for the acceleration timestep criterion of a particle, never 0
+    pub fn get_timestep_length(&self, length: f32) -> f32 {
+        if self.kernel == NO_SOFTENING {
+            return EPS;
+        }
+        return length.max(EPS);
+    }
+
+    // past this distance every kernel is treated as plain 1/r
+    pub fn get_support(&self, length: f32) -> f32 {
+        if self.kernel == NO_SOFTENING {
+            return 0.0;
+        }
+        if self.kernel == PLUMMER_SOFTENING {
+            return PLUMMER_SUPPORT * length;
+        }
+        return SPLINE_SUPPORT * length;
+    }
+
+    pub fn force_factor(&self, distance_squared: f32, length: f32) -> f32 {
+        if self.kernel == PLUMMER_SOFTENING {
+            let soft_squared = distance_squared + length * length;
+            if soft_squared == 0.0 {
+                return 0.0;
+            }
+            return 1.0 / (soft_squared * soft_squared.sqrt());
+        }
+
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+
+        let distance = distance_squared.sqrt();
+        let h = SPLINE_SUPPORT * length;
+
+        if self.kernel == SPLINE_SOFTENING && distance < h {
+            let u = distance / h;
+            let h3_inv = 1.0 / (h * h * h);
+
+            if u < 0.5 {
+                return h3_inv * (10.666667 + u * u * (32.0 * u - 38.4));
+            }
+            return h3_inv
+                * (21.333334 - 48.0 * u + 38.4 * u * u
+                    - 10.666667 * u * u * u
+                    - 0.06666667 / (u * u * u));
+        }
+
+        return 1.0 / (distance_squared * distance);
+    }
+
+    pub fn potential_factor(&self, distance_squared: f32, length: f32) -> f32 {
+        if self.kernel == PLUMMER_SOFTENING {
+            let soft_squared = distance_squared + length * length;
+            if soft_squared == 0.0 {
+                return 0.0;
+            }
+            return 1.0 / soft_squared.sqrt();
+        }
+
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+
+        let distance = distance_squared.sqrt();
+        let h = SPLINE_SUPPORT * length;
+
+        if self.kernel == SPLINE_SOFTENING && distance < h {
+            let u = distance / h;
+
+            if u < 0.5 {
+                return (2.8 - u * u * (5.3333335 + u * u * (6.4 * u - 9.6))) / h;
+            }
+            return (3.2
+                - 0.06666667 / u
+                - u * u * (10.666667 + u * (-16.0 + u * (9.6 - 2.1333334 * u))))
+                / h;
+        }
+
+        return 1.0 / distance;
+    }
+
+    // 2 d(force_factor) / d(r^2), the jerk of a pair is m * (force_factor * dv + this * (r.v) * r)
+    pub fn jerk_factor(&self, distance_squared: f32, length: f32) -> f32 {
+        if self.kernel == PLUMMER_SOFTENING {
+            let soft_squared = distance_squared + length * length;
+            if soft_squared == 0.0 {
+                return 0.0;
+            }
+            return -3.0 / (soft_squared * soft_squared * soft_squared.sqrt());
+        }
+
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+
+        let distance = distance_squared.sqrt();
+        let h = SPLINE_SUPPORT * length;
+
+        if self.kernel == SPLINE_SOFTENING && distance < h {
+            let u = distance / h;
+            let h5_inv = 1.0 / (h * h * h * h * h);
+
+            if u < 0.5 {
+                return h5_inv * (96.0 * u - 76.8);
+            }
+            return h5_inv * (-48.0 / u + 76.8 - 32.0 * u + 0.2 / (u * u * u * u * u));
+        }
+
+        return -3.0 / (distance_squared * distance_squared * distance);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KERNELS: [u32; 3] = [NO_SOFTENING, PLUMMER_SOFTENING, SPLINE_SOFTENING];
+
+    // distances in softening lengths, through both halves of the spline and past its support
+    const DISTANCES: [f32; 7] = [0.3, 0.9, 1.3, 2.0, 2.7, 3.5, 10.0];
+
+    fn is_close(a: f32, b: f32) -> bool {
+        return (a - b).abs() <= 1e-3 * a.abs().max(b.abs());
+    }
+
+    #[test]
+    fn force_is_the_potential_gradient() {
+        let length = 0.01;
+        for kernel in KERNELS {
+            let softening = Softening::new(kernel, length);
+            for distance in DISTANCES.map(|distance| distance * length) {
+                let h = distance * 1e-3;
+                let slope = (softening.potential_factor((distance + h).powi(2), length)
+                    - softening.potential_factor((distance - h).powi(2), length))
+                    / (2.0 * h);
+                let force = softening.force_factor(distance * distance, length) * distance;
+                assert!(
+                    is_close(force, -slope),
+                    "kernel {} at {}: force {} slope {}",
+                    kernel,
+                    distance,
+                    force,
+                    -slope
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn jerk_is_the_force_derivative() {
+        let length = 0.01;
+        for kernel in KERNELS {
+            let softening = Softening::new(kernel, length);
+            for distance in DISTANCES.map(|distance| distance * length) {
+                let h = distance * 1e-3;
+                let slope = (softening.force_factor((distance + h).powi(2), length)
+                    - softening.force_factor((distance - h).powi(2), length))
+                    / (2.0 * h);
+                let jerk = softening.jerk_factor(distance * distance, length) * distance;
+                assert!(
+                    is_close(jerk, slope),
+                    "kernel {} at {}: jerk {} slope {}",
+                    kernel,
+                    distance,
+                    jerk,
+                    slope
+                );
+            }
+        }
+    }
+
+    // plummer and the spline agree on the potential at the center, past the support the spline
+    // is plain 1/r
+    #[test]
+    fn spline_matches_plummer_center_and_newton_outside() {
+        let length = 0.01;
+        let plummer = Softening::new(PLUMMER_SOFTENING, length);
+        let spline = Softening::new(SPLINE_SOFTENING, length);
+        let newton = Softening::new(NO_SOFTENING, length);
+
+        let center = 1e-4 * length;
+        assert!(is_close(
+            spline.potential_factor(center * center, length),
+            plummer.potential_factor(center * center, length)
+        ));
+
+        let outside = 1.01 * spline.get_support(length);
+        assert!(is_close(
+            spline.force_factor(outside * outside, length),
+            newton.force_factor(outside * outside, length)
+        ));
+    }
+}
@@ -0,0 +1,201 @@
+use crate::drag::Drag;
+use crate::particle::Particle;
+use crate::size_distribution::SizeDistribution;
+
+// the interaction matrices are one bit per species
+pub const MAX_SPECIES: usize = 32;
+
+// everything a particle of this kind starts with. species 0 is always there and is what
+// Particle::new() gives
+#[derive(Clone)]
+pub struct Species {
+    // no spaces, it goes into a space separated file
+    pub name: String,
+    // only for the renderer
+    pub color: [u8; 3],
+    pub mass: f32,
+    pub radius: f32,
+    // 0 keeps the mass, otherwise the mass comes from the radius like Particle::set_density
+    pub density: f32,
+    // 0 uses the global softening length
+    pub softening: f32,
+    // fixed gives every particle exactly mass and radius
+    pub size_distribution: SizeDistribution,
+    // sph particles, starting with internal_energy per unit mass
+    pub is_gas: bool,
+    pub internal_energy: f32,
+    // friction against SimulationSpecs::set_background_flow
+    pub drag: Drag,
+    // black holes and neutron stars, see SimulationSpecs::set_post_newtonian
+    pub is_compact: bool,
+    // sinks from the start, a central black hole say. see SinkFormation for the ones that form
+    pub is_sink: bool,
+    pub accretion_radius: f32,
+    // probes that feel gravity without making any, recorded on their own
+    pub is_tracer: bool,
+    pub is_tracer_colliding: bool,
+}
+
+impl Species {
+    pub fn new(name: &str, color: [u8; 3], mass: f32, radius: f32) -> Species {
+        return Species {
+            name: name.to_string(),
+            color,
+            mass,
+            radius,
+            density: 0.0,
+            softening: 0.0,
+            size_distribution: SizeDistribution::fixed(),
+            is_gas: false,
+            internal_energy: 0.0,
+            drag: Drag::disabled(),
+            is_compact: false,
+            is_sink: false,
+            accretion_radius: 0.0,
+            is_tracer: false,
+            is_tracer_colliding: false,
+        };
+    }
+
+    pub fn default() -> Species {
+        let particle = Particle::new();
+        return Species::new("default", [200, 20, 20], particle.mass, particle.radius);
+    }
+
+    // bits for the first n_species species
+    pub fn mask(n_species: usize) -> u32 {
+        if n_species >= MAX_SPECIES {
+            return u32::MAX;
+        }
+        return (1 << n_species) - 1;
+    }
+
+    pub fn set_density(&mut self, density: f32) {
+        self.density = density;
+    }
+
+    pub fn set_softening(&mut self, softening: f32) {
+        self.softening = softening;
+    }
+
+    // gas usually should not collide, see SimulationSpecs::set_collision_interaction. it also
+    // wants a softening around its smoothing length, two gas particles can pass right through
+    // each other and the default one is far too small for that
+    pub fn set_gas(&mut self, internal_energy: f32) {
+        self.is_gas = true;
+        self.internal_energy = internal_energy;
+    }
+
+    // the stopping time is for a particle of the species radius unless the drag has its own
+    pub fn set_drag(&mut self, mut drag: Drag) {
+        if drag.reference_radius <= 0.0 {
+            drag.reference_radius = self.radius;
+        }
+        self.drag = drag;
+    }
+
+    // the post newtonian terms assume point masses, so compact species want no softening
+    // between each other either
+    pub fn set_compact(&mut self) {
+        self.is_compact = true;
+    }
+
+    pub fn set_sink(&mut self, accretion_radius: f32) {
+        self.is_sink = true;
+        self.accretion_radius = accretion_radius;
+    }
+
+    // colliding tracers bounce off everything but only ever move themselves and each other
+    pub fn set_tracer(&mut self, is_colliding: bool) {
+        self.is_tracer = true;
+        self.is_tracer_colliding = is_colliding;
+    }
+
+    pub fn set_size_distribution(&mut self, size_distribution: SizeDistribution) {
+        self.size_distribution = size_distribution;
+    }
+
+    // the given density, or the one of the species mass and radius
+    pub fn get_density(&self) -> f32 {
+        if self.density > 0.0 {
+            return self.density;
+        }
+        return self.mass / (self.radius * self.radius * self.radius * self.radius);
+    }
+
+    pub fn make_particle(&self, species_i: u32) -> Particle {
+        let mut particle = Particle::new();
+        particle.species = species_i;
+        particle.radius = self.radius;
+        particle.softening = self.softening;
+        particle.is_gas = self.is_gas;
+        particle.internal_energy = self.internal_energy;
+        particle.is_compact = self.is_compact;
+        particle.is_sink = self.is_sink;
+        particle.accretion_radius = self.accretion_radius;
+        particle.is_tracer = self.is_tracer;
+
+        if !self.size_distribution.is_fixed() {
+            let size = self.size_distribution.sample();
+            if self.size_distribution.is_mass {
+                particle.mass = size;
+                particle.set_radius_from_density(self.get_density());
+            } else {
+                particle.radius = size;
+                particle.set_density(self.get_density());
+            }
+        } else if self.density > 0.0 {
+            particle.set_density(self.density);
+        } else {
+            particle.mass = self.mass;
+            particle.update_inertia();
+        }
+
+        return particle;
+    }
+
+    // "index name r g b" per line
+    pub fn to_line(&self, species_i: usize) -> String {
+        return format!(
+            "{} {} {} {} {}",
+            species_i, self.name, self.color[0], self.color[1], self.color[2]
+        );
+    }
+}
+
+// which species collide with which and which feel gravity from which. row i holds a bit for
+// every species j that i interacts with, everything interacts with everything by default
+#[derive(Clone)]
+pub struct InteractionMatrix {
+    rows: Vec<u32>,
+}
+
+impl InteractionMatrix {
+    pub fn new() -> InteractionMatrix {
+        return InteractionMatrix {
+            rows: vec![u32::MAX; MAX_SPECIES],
+        };
+    }
+
+    pub fn set(&mut self, species_i: u32, species_j: u32, is_interacting: bool) {
+        if is_interacting {
+            self.rows[species_i as usize] |= 1 << species_j;
+        } else {
+            self.rows[species_i as usize] &= !(1 << species_j);
+        }
+    }
+
+    pub fn get(&self, species_i: u32, species_j: u32) -> bool {
+        return self.rows[species_i as usize] & (1 << species_j) != 0;
+    }
+
+    // true if any of the species in mask interacts with species_i
+    pub fn any(&self, species_i: u32, mask: u32) -> bool {
+        return self.rows[species_i as usize] & mask != 0;
+    }
+
+    pub fn is_all(&self, n_species: usize) -> bool {
+        let mask = Species::mask(n_species);
+        return self.rows[..n_species].iter().all(|row| row & mask == mask);
+    }
+}
@@ -0,0 +1,271 @@
+use crate::particle::Particle;
+use crate::qtree::{Bound, QuadTree};
+use crate::vector::Vec2;
+
+use std::f32::consts::PI;
+
+// p = sound_speed^2 rho, no internal energy evolution
+pub const ISOTHERMAL_EOS: u32 = 0;
+// p = (gamma - 1) rho u
+pub const ADIABATIC_EOS: u32 = 1;
+
+// 2d cubic spline, reaches 0 at 2h
+fn kernel(distance: f32, h: f32) -> f32 {
+    let q = distance / h;
+    let sigma = 10.0 / (7.0 * PI * h * h);
+
+    if q < 1.0 {
+        return sigma * (1.0 - 1.5 * q * q + 0.75 * q * q * q);
+    } else if q < 2.0 {
+        return sigma * 0.25 * (2.0 - q) * (2.0 - q) * (2.0 - q);
+    }
+    return 0.0;
+}
+
+// dW/dr
+fn kernel_derivative(distance: f32, h: f32) -> f32 {
+    let q = distance / h;
+    let sigma = 10.0 / (7.0 * PI * h * h * h);
+
+    if q < 1.0 {
+        return sigma * (-3.0 * q + 2.25 * q * q);
+    } else if q < 2.0 {
+        return sigma * -0.75 * (2.0 - q) * (2.0 - q);
+    }
+    return 0.0;
+}
+
+// smoothed particle hydrodynamics for the gas species. the smoothing length of a particle is
+// half the distance to its n_neighbours-th closest gas particle, so the kernel always covers
+// about n_neighbours of them. pairs use the average of both kernel gradients which keeps the
+// pressure forces symmetric, with monaghan artificial viscosity on approaching pairs
+#[derive(Clone)]
+pub struct Sph {
+    pub equation_of_state: u32,
+    pub gamma: f32,
+    // only for the isothermal equation of state
+    pub sound_speed: f32,
+    pub n_neighbours: u32,
+    // artificial viscosity, linear and quadratic in the approach speed
+    pub alpha: f32,
+    pub beta: f32,
+
+    // gas only, the main quadtree has every species in it
+    tree: QuadTree,
+    gas_particles: Vec<usize>,
+    // every gas particle within 2h of either side of the pair
+    neighbours: Vec<Vec<usize>>,
+    candidates: Vec<usize>,
+}
+
+impl Sph {
+    pub fn new(equation_of_state: u32, gamma: f32, sound_speed: f32) -> Sph {
+        return Sph {
+            equation_of_state,
+            gamma,
+            sound_speed,
+            n_neighbours: 16,
+            alpha: 1.0,
+            beta: 2.0,
+            tree: QuadTree::new(),
+            gas_particles: Vec::new(),
+            neighbours: Vec::new(),
+            candidates: Vec::new(),
+        };
+    }
+
+    pub fn isothermal(sound_speed: f32) -> Sph {
+        return Sph::new(ISOTHERMAL_EOS, 1.0, sound_speed);
+    }
+
+    pub fn adiabatic(gamma: f32) -> Sph {
+        return Sph::new(ADIABATIC_EOS, gamma, 0.0);
+    }
+
+    pub fn set_n_neighbours(&mut self, n_neighbours: u32) {
+        self.n_neighbours = n_neighbours;
+    }
+
+    pub fn set_viscosity(&mut self, alpha: f32, beta: f32) {
+        self.alpha = alpha;
+        self.beta = beta;
+    }
+
+    pub fn pressure(&self, particle: &Particle) -> f32 {
+        if self.equation_of_state == ISOTHERMAL_EOS {
+            return self.sound_speed * self.sound_speed * particle.gas_density;
+        }
+        return (self.gamma - 1.0) * particle.gas_density * particle.internal_energy;
+    }
+
+    pub fn get_sound_speed(&self, particle: &Particle) -> f32 {
+        if self.equation_of_state == ISOTHERMAL_EOS {
+            return self.sound_speed;
+        }
+        return (self.gamma * (self.gamma - 1.0) * particle.internal_energy)
+            .max(0.0)
+            .sqrt();
+    }
+
+    // k T / (mu m_h) in velocity squared units
+    pub fn temperature(&self, particle: &Particle) -> f32 {
+        if !particle.is_gas {
+            return 0.0;
+        }
+        if self.equation_of_state == ISOTHERMAL_EOS {
+            return self.sound_speed * self.sound_speed;
+        }
+        return (self.gamma - 1.0) * particle.internal_energy;
+    }
+
+    // smoothing lengths, densities and pressures for every gas particle, then the pressure
+    // and viscous accelerations and du/dt for the active ones
+    pub fn compute(&mut self, particles: &mut Vec<Particle>) {
+        self.gas_particles.clear();
+        for (particle_i, particle) in particles.iter().enumerate() {
+            if particle.is_gas && !particle.is_consumed {
+                self.gas_particles.push(particle_i);
+            }
+        }
+        if self.gas_particles.is_empty() {
+            return;
+        }
+
+        self.tree.reset();
+        for &particle_i in &self.gas_particles {
+            self.tree.add_particle(particles, particle_i);
+        }
+
+        // neighbours are found with each particle's own 2h, so the pairs only the other side
+        // can see get added afterwards. checked by list rather than by distance since the tree
+        // drops one of two particles that end up in the same deepest node
+        self.neighbours.resize(particles.len(), Vec::new());
+        for &particle_i in &self.gas_particles {
+            let pos = particles[particle_i].pos;
+            let distance = self.tree.get_neighbour_distance(
+                particles,
+                pos,
+                self.n_neighbours as usize,
+                &mut self.candidates,
+            );
+            let h = (distance * 0.5).max(f32::MIN_POSITIVE);
+            particles[particle_i].smoothing_length = h;
+
+            let corner = Vec2::new(2.0 * h, 2.0 * h);
+            self.candidates.clear();
+            self.tree.idx_bound(
+                &Bound::new(pos - corner, pos + corner),
+                &mut self.candidates,
+            );
+
+            let neighbours = &mut self.neighbours[particle_i];
+            neighbours.clear();
+            for &other_i in &self.candidates {
+                if (particles[other_i].pos - pos).length_squared() < 4.0 * h * h {
+                    neighbours.push(other_i);
+                }
+            }
+        }
+
+        for gas_i in 0..self.gas_particles.len() {
+            let particle_i = self.gas_particles[gas_i];
+            for neighbour_ii in 0..self.neighbours[particle_i].len() {
+                let other_i = self.neighbours[particle_i][neighbour_ii];
+                if !self.neighbours[other_i].contains(&particle_i) {
+                    self.neighbours[other_i].push(particle_i);
+                }
+            }
+        }
+
+        for &particle_i in &self.gas_particles {
+            let pos = particles[particle_i].pos;
+            let h = particles[particle_i].smoothing_length;
+
+            // self contribution included
+            let mut density = particles[particle_i].mass * kernel(0.0, h);
+            for &other_i in &self.neighbours[particle_i] {
+                if other_i != particle_i {
+                    let distance = (particles[other_i].pos - pos).length();
+                    density += particles[other_i].mass * kernel(distance, h);
+                }
+            }
+
+            particles[particle_i].gas_density = density;
+            particles[particle_i].pressure = self.pressure(&particles[particle_i]);
+            particles[particle_i].sound_speed = self.get_sound_speed(&particles[particle_i]);
+        }
+
+        for &particle_i in &self.gas_particles {
+            if !particles[particle_i].is_active {
+                continue;
+            }
+
+            let particle = &particles[particle_i];
+            let pressure_term = particle.pressure / (particle.gas_density * particle.gas_density);
+
+            let mut acc = Vec2::zero();
+            let mut du_dt = 0.0;
+            let mut signal_speed = particle.sound_speed;
+
+            for &other_i in &self.neighbours[particle_i] {
+                if other_i == particle_i {
+                    continue;
+                }
+                let other = &particles[other_i];
+
+                let delta = particle.pos - other.pos;
+                let distance = delta.length();
+                if distance == 0.0 {
+                    continue;
+                }
+
+                let gradient = delta
+                    * (0.5
+                        * (kernel_derivative(distance, particle.smoothing_length)
+                            + kernel_derivative(distance, other.smoothing_length))
+                        / distance);
+
+                let rel_vel = particle.vel - other.vel;
+                let approach = rel_vel.dot(delta);
+
+                let mut viscosity = 0.0;
+                if approach < 0.0 {
+                    signal_speed = signal_speed
+                        .max(particle.sound_speed + other.sound_speed - 3.0 * approach / distance);
+                    let h = 0.5 * (particle.smoothing_length + other.smoothing_length);
+                    let mu = h * approach / (distance * distance + 0.01 * h * h);
+                    let mean_sound_speed = 0.5 * (particle.sound_speed + other.sound_speed);
+                    let mean_density = 0.5 * (particle.gas_density + other.gas_density);
+                    viscosity =
+                        (-self.alpha * mean_sound_speed * mu + self.beta * mu * mu) / mean_density;
+                }
+
+                let other_pressure_term = other.pressure / (other.gas_density * other.gas_density);
+                acc -= gradient * (other.mass * (pressure_term + other_pressure_term + viscosity));
+                du_dt += other.mass * (pressure_term + 0.5 * viscosity) * rel_vel.dot(gradient);
+            }
+
+            particles[particle_i].acc += acc;
+            particles[particle_i].signal_speed = signal_speed;
+            if self.equation_of_state == ADIABATIC_EOS {
+                particles[particle_i].du_dt += du_dt;
+            }
+        }
+    }
+
+    // sum of m u over the gas
+    pub fn thermal_energy(&self, particles: &Vec<Particle>) -> f32 {
+        if self.equation_of_state == ISOTHERMAL_EOS {
+            return 0.0;
+        }
+
+        let mut energy = 0.0;
+        for particle in particles {
+            if particle.is_gas {
+                energy += particle.mass * particle.internal_energy;
+            }
+        }
+
+        return energy;
+    }
+}
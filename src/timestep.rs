@@ -0,0 +1,134 @@
+use crate::constants::SPH_COURANT;
+use crate::particle::Particle;
+use crate::softening::Softening;
+use crate::vector::Vec2;
+
+// sqrt(2 * eta * length / |a|), the usual softening based criterion
+pub fn acceleration_timestep(acc: Vec2, length: f32, accuracy: f32) -> f32 {
+    let acc_length = acc.length();
+    if acc_length == 0.0 {
+        return f32::MAX;
+    }
+
+    return (2.0 * accuracy * length / acc_length).sqrt();
+}
+
+// bin 0 steps with max_dt, every bin after that halves it
+pub fn timestep_bin(max_dt: f32, dt: f32, n_levels: u32) -> u32 {
+    if dt >= max_dt {
+        return 0;
+    }
+
+    let bin = (max_dt / dt).log2().ceil() as u32;
+    return bin.min(n_levels);
+}
+
+// how many of the smallest steps a particle in this bin takes per step
+pub fn bin_step_count(bin: u32, n_levels: u32) -> u32 {
+    return 1 << (n_levels - bin);
+}
+
+// a particle should not move more than courant * radius in one step, otherwise it can skip
+// straight past the particle it was about to hit
+pub fn courant_timestep(particle: &Particle, courant: f32) -> f32 {
+    let speed = particle.get_speed();
+    if speed == 0.0 {
+        return f32::MAX;
+    }
+
+    return courant * particle.radius / speed;
+}
+
+// signal crossing time of the smoothing length for gas, the hydro equivalent of the above
+pub fn sph_timestep(particle: &Particle, courant: f32) -> f32 {
+    if !particle.is_gas || particle.signal_speed == 0.0 {
+        return f32::MAX;
+    }
+
+    return courant * particle.smoothing_length / particle.signal_speed;
+}
+
+// the length acceleration_timestep uses, gas resolves nothing below its smoothing length
+pub fn timestep_length(particle: &Particle, softening: &Softening) -> f32 {
+    let length = softening.get_timestep_length(particle.softening);
+    if particle.is_gas {
+        return length.max(particle.smoothing_length);
+    }
+    return length;
+}
+
+// global step for the next sub step, the strictest particle wins
+pub fn adaptive_timestep(
+    particles: &Vec<Particle>,
+    softening: &Softening,
+    accuracy: f32,
+    courant: f32,
+    min_dt: f32,
+    max_dt: f32,
+) -> f32 {
+    let mut dt = max_dt;
+
+    for particle in particles {
+        let length = timestep_length(particle, softening);
+        dt = dt.min(acceleration_timestep(particle.acc, length, accuracy));
+        dt = dt.min(courant_timestep(particle, courant));
+        dt = dt.min(sph_timestep(particle, SPH_COURANT));
+    }
+
+    return dt.clamp(min_dt, max_dt);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::GRAVITY_CONST;
+    use crate::particle_container::Container;
+
+    // two particles at rest, the step has to come out of the direct sum acceleration
+    #[test]
+    fn follows_the_direct_sum_acceleration() {
+        let mut container = Container::new();
+        for (pos, mass) in [(Vec2::new(-0.05, 0.0), 2.0), (Vec2::new(0.05, 0.0), 1.0)] {
+            let mut particle = Particle::new();
+            particle.set_pos(pos);
+            particle.mass = mass;
+            container.push_particle(particle);
+        }
+        container.reset_acc();
+        container.interparticle_gravity_quadratic();
+
+        let accuracy = 0.02;
+        let dt = adaptive_timestep(
+            &container.particles,
+            &container.softening,
+            accuracy,
+            0.1,
+            0.0,
+            1.0,
+        );
+
+        // the lighter particle feels the heavier one
+        let length = container.softening.get_length();
+        let acc = GRAVITY_CONST * 2.0 / (0.1 * 0.1);
+        let expected = (2.0 * accuracy * length / acc).sqrt();
+        assert!(
+            (dt - expected).abs() < 1e-3 * expected,
+            "dt {} expected {}",
+            dt,
+            expected
+        );
+    }
+
+    #[test]
+    fn bins_never_step_past_the_timestep() {
+        let max_dt = 0.1;
+        let n_levels = 4;
+        for dt in [0.2, 0.1, 0.07, 0.05, 0.031, 0.013, 0.001] {
+            let bin = timestep_bin(max_dt, dt, n_levels);
+            let bin_dt = max_dt / (1 << bin) as f32;
+            assert!(bin_dt <= dt || bin == n_levels, "dt {} got bin {}", dt, bin);
+            assert!(bin == 0 || bin_dt * 2.0 > dt, "dt {} got bin {}", dt, bin);
+            assert_eq!(bin_step_count(bin, n_levels) << bin, 1 << n_levels);
+        }
+    }
+}
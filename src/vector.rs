@@ -23,10 +23,21 @@ impl Vec2 {
         return self.x * other.x + self.y * other.y;
     }
 
+    // z component of the 3d cross product
+    pub fn cross(&self, other: Vec2) -> f32 {
+        return self.x * other.y - self.y * other.x;
+    }
+
     pub fn perp(&self) -> Vec2 {
         return Vec2::new(-self.y, self.x);
     }
 
+    // counter clockwise
+    pub fn rotate(&self, angle: f32) -> Vec2 {
+        let (sin, cos) = angle.sin_cos();
+        return Vec2::new(self.x * cos - self.y * sin, self.x * sin + self.y * cos);
+    }
+
     pub fn length(&self) -> f32 {
         return (self.length_squared()).sqrt();
     }